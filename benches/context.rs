@@ -0,0 +1,68 @@
+use context_attribute::__private::{Contextable, Message};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use failure::{err_msg, Error};
+
+fn fail() -> Result<(), Error> {
+    Err(err_msg("oh no"))
+}
+
+fn bench_context(c: &mut Criterion) {
+    c.bench_function("static context", |b| {
+        b.iter(|| {
+            black_box(fail()).apply_context(|| Message::Borrowed("Read address.txt from disk"))
+        })
+    });
+
+    c.bench_function("computed context", |b| {
+        let path = "address.txt";
+        b.iter(|| {
+            black_box(fail())
+                .apply_context(|| Message::Owned(format!("Read {} from disk", black_box(path))))
+        })
+    });
+
+    c.bench_function("static context on success", |b| {
+        b.iter(|| {
+            black_box(Ok::<_, Error>(()))
+                .apply_context(|| Message::Borrowed("Read address.txt from disk"))
+        })
+    });
+}
+
+#[cfg(feature = "anyhow")]
+fn bench_anyhow(c: &mut Criterion) {
+    use anyhow::Context;
+
+    fn fail() -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("oh no"))
+    }
+
+    c.bench_function("anyhow static context", |b| {
+        b.iter(|| {
+            black_box(fail()).apply_context(|| Message::Borrowed("Read address.txt from disk"))
+        })
+    });
+
+    c.bench_function("anyhow static context by hand", |b| {
+        b.iter(|| black_box(fail()).context("Read address.txt from disk"))
+    });
+
+    c.bench_function("anyhow computed context", |b| {
+        let path = "address.txt";
+        b.iter(|| {
+            black_box(fail())
+                .apply_context(|| Message::Owned(format!("Read {} from disk", black_box(path))))
+        })
+    });
+
+    c.bench_function("anyhow computed context by hand", |b| {
+        let path = "address.txt";
+        b.iter(|| black_box(fail()).with_context(|| format!("Read {} from disk", black_box(path))))
+    });
+}
+
+#[cfg(not(feature = "anyhow"))]
+criterion_group!(benches, bench_context);
+#[cfg(feature = "anyhow")]
+criterion_group!(benches, bench_context, bench_anyhow);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+use context_attribute::context;
+use failure::{ensure, Error};
+use futures::executor::block_on;
+
+#[async_trait]
+trait Square {
+    async fn square(&self, num: usize) -> Result<usize, Error>;
+}
+
+struct Squarer {
+    max: usize,
+}
+
+// `#[async_trait]` goes on the impl block and `#[context]` on the method. The impl block's
+// attribute expands first, so `#[context]` sees the boxed future it produces.
+#[async_trait]
+impl Square for Squarer {
+    /// Square a number if it's less than {self.max}.
+    #[context]
+    async fn square(&self, num: usize) -> Result<usize, Error> {
+        ensure!(num < self.max, "Number was too large");
+        Ok(num * num)
+    }
+}
+
+fn main() -> Result<(), Error> {
+    let squarer = Squarer { max: 10 };
+    println!("result is {}", block_on(squarer.square(3))?);
+    println!("result is {}", block_on(squarer.square(12))?);
+    Ok(())
+}
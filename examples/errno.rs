@@ -0,0 +1,30 @@
+//! Wrap a C function that reports errors through `errno`.
+
+use context_attribute::context;
+use failure::Error;
+use std::os::raw::c_int;
+
+#[cfg(unix)]
+extern "C" {
+    fn close(fd: c_int) -> c_int;
+}
+
+/// Close file descriptor {fd}
+#[cfg(unix)]
+#[context(errno)]
+fn close_fd(fd: c_int) -> Result<c_int, Error> {
+    unsafe { close(fd) }
+}
+
+#[cfg(unix)]
+fn main() {
+    // There's no file descriptor -1, so this fails with `EBADF`.
+    if let Err(err) = close_fd(-1) {
+        for cause in err.iter_chain() {
+            eprintln!("{}", cause);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {}
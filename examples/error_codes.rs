@@ -0,0 +1,22 @@
+//! Look the contexts up in a table of error codes, `error_codes.tsv`.
+//!
+//! `.cargo/config.toml` points `CONTEXT_ATTRIBUTE_CODES` at the table.
+
+use context_attribute::context;
+use failure::Error;
+
+#[context(catalog = "E1001")]
+fn read_config(path: &str) -> Result<String, Error> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+#[context(catalog = "E1002")]
+fn parse_port(input: &str) -> Result<u16, Error> {
+    Ok(input.trim().parse()?)
+}
+
+fn main() -> Result<(), Error> {
+    let config = read_config("port.txt")?;
+    println!("listening on port {}", parse_port(&config)?);
+    Ok(())
+}
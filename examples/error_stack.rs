@@ -0,0 +1,25 @@
+use context_attribute::context;
+use error_stack::{Report, ResultExt};
+use std::fmt;
+
+#[derive(Debug)]
+struct ConfigError;
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the config is invalid")
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Read the config from {path}
+#[context]
+fn read_config(path: &str) -> Result<String, Report<ConfigError>> {
+    std::fs::read_to_string(path).change_context(ConfigError)
+}
+
+fn main() -> Result<(), Report<ConfigError>> {
+    println!("{}", read_config("config.toml")?);
+    Ok(())
+}
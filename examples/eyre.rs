@@ -0,0 +1,27 @@
+use context_attribute::context;
+use eyre::Result;
+
+/// Read the config from {path}
+#[context(note = "check that {path} exists, or pass a different path")]
+fn read_config(path: &str) -> Result<String> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Load the {name} plugin
+///
+/// Plugins are looked up in the `plugins` directory next to the config.
+///
+/// Run `fetch-plugins` to download the default plugins.
+#[context(notes)]
+fn load_plugin(name: &str) -> Result<String> {
+    Ok(std::fs::read_to_string(format!("plugins/{}.toml", name))?)
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    match std::env::args().nth(1) {
+        Some(plugin) => println!("{}", load_plugin(&plugin)?),
+        None => println!("{}", read_config("config.toml")?),
+    }
+    Ok(())
+}
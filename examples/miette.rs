@@ -0,0 +1,33 @@
+use context_attribute::context;
+use miette::{Diagnostic, NamedSource, Result, SourceSpan};
+use thiserror::Error;
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("invalid port")]
+#[diagnostic(code(config::port), help("ports are numbers between 1 and 65535"))]
+struct PortError {
+    #[source_code]
+    src: NamedSource<String>,
+    #[label("this isn't a number")]
+    span: SourceSpan,
+}
+
+/// Parse the port in {name}
+#[context]
+fn parse_port(name: &str, config: &str) -> Result<u16> {
+    let value = config.strip_prefix("port = ").unwrap_or(config).trim();
+    value.parse().map_err(|_| {
+        let offset = config.find(value).unwrap_or(0);
+        let err = PortError {
+            src: NamedSource::new(name, config.to_string()),
+            span: (offset, value.len()).into(),
+        };
+        err.into()
+    })
+}
+
+fn main() -> Result<()> {
+    let port = parse_port("server.toml", "port = eighty")?;
+    println!("listening on port {}", port);
+    Ok(())
+}
@@ -1,9 +1,6 @@
 use context_attribute::context;
 use failure::{Error, ResultExt};
 
-use std::fs;
-use std::net::SocketAddr;
-
 /// Read address.txt from disk
 fn read_file_1() -> Result<String, Error> {
     let res = std::fs::read_to_string("address.txt")
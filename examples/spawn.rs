@@ -0,0 +1,24 @@
+use context_attribute::context;
+use failure::{ensure, Error};
+
+fn load(id: u32) -> Result<String, Error> {
+    ensure!(id != 0, "no user with id {}", id);
+    Ok(format!("user {}", id))
+}
+
+/// Fetch the profile of user {id}
+#[context]
+async fn fetch_profile(id: u32) -> Result<String, Error> {
+    let task = tokio::spawn(async move { load(id) });
+    // Errors from joining the task and from the task itself both propagate through `?` and get
+    // the context, even though the task ran on another thread.
+    let profile = task.await??;
+    Ok(profile)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    println!("{}", fetch_profile(1).await?);
+    println!("{}", fetch_profile(0).await?);
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use context_attribute::context;
-use failure::{ensure, ResultExt};
+use failure::ensure;
 
 /// Square a number if it's less than 10.
 #[context]
@@ -9,9 +9,9 @@ fn square(num: usize) -> Result<usize, failure::Error> {
 }
 
 fn main() -> Result<(), failure::Error> {
-    let args = std::env::args();
+    let mut args = std::env::args();
     ensure!(args.len() == 2, "usage: square <num>");
-    let input = args.skip(1).next().unwrap().parse()?;
+    let input = args.nth(1).unwrap().parse()?;
 
     println!("result is {}", square(input)?);
 
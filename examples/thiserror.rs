@@ -0,0 +1,27 @@
+use context_attribute::context;
+
+#[derive(Debug, thiserror::Error)]
+enum ConfigError {
+    #[error("{0}")]
+    Io(String, #[source] std::io::Error),
+    #[error("{0}")]
+    Parse(String, #[source] std::num::ParseIntError),
+}
+
+/// Read the config from {path}
+#[context(variant = ConfigError::Io)]
+fn read_config(path: &str) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path)
+}
+
+/// Parse the port from {input:?}
+#[context(variant = ConfigError::Parse)]
+fn parse_port(input: &str) -> Result<u16, ConfigError> {
+    input.trim().parse()
+}
+
+fn main() -> Result<(), ConfigError> {
+    let config = read_config("port.txt")?;
+    println!("listening on port {}", parse_port(&config)?);
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use context_attribute::context;
+use failure::{ensure, Error};
+
+async fn connect(addr: &str) -> Result<(), Error> {
+    ensure!(!addr.is_empty(), "no address to connect to");
+    Ok(())
+}
+
+/// Run the server
+#[context]
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let addr = std::env::args().nth(1).unwrap_or_default();
+    connect(&addr).await?;
+    println!("listening on {}", addr);
+    Ok(())
+}
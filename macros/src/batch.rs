@@ -0,0 +1,138 @@
+//! Rewriting the `for` loops of a function body for `batch`.
+
+use proc_macro2::TokenStream;
+use quote::quote_spanned;
+use syn::spanned::Spanned;
+use syn::visit_mut::VisitMut;
+
+/// The flag that records whether an item's error already got a context, so the function's own
+/// isn't attached on top of it.
+pub(crate) fn item_failed() -> syn::Ident {
+    quote::format_ident!("item_failed", span = proc_macro2::Span::mixed_site())
+}
+
+/// Rewrite each `for` loop in the body, though not those nested in other expressions, so a `?` in
+/// it attaches the message with the index of the current item, as in `Import the users: item 2`.
+///
+/// A `?` becomes an early return of the function's return type, so the error is converted with
+/// `From` the same way. `?`s in closures, `async` blocks, and macro invocations are left as-is.
+pub(crate) fn batched(
+    stmts: &[syn::Stmt],
+    message: &TokenStream,
+    ty: &syn::Type,
+) -> syn::Result<Vec<syn::Stmt>> {
+    let counter = quote::format_ident!("item_counter", span = proc_macro2::Span::mixed_site());
+    let index = quote::format_ident!("item_index", span = proc_macro2::Span::mixed_site());
+    let mut loops = 0;
+    let mut batched = vec![];
+    for stmt in stmts {
+        let mut stmt = stmt.clone();
+        if let syn::Stmt::Expr(syn::Expr::ForLoop(for_loop), _) = &mut stmt {
+            let mut items = Items {
+                message,
+                ty,
+                index: &index,
+            };
+            items.visit_block_mut(&mut for_loop.body);
+            for_loop.body.stmts.insert(
+                0,
+                syn::parse_quote! {
+                    let #index = {
+                        #counter += 1;
+                        #counter - 1
+                    };
+                },
+            );
+            batched.push(syn::parse_quote!(let mut #counter = 0usize;));
+            loops += 1;
+        }
+        batched.push(stmt);
+    }
+    if loops == 0 {
+        let msg = "`batch` requires a `for` loop in the function body";
+        return Err(syn::Error::new(proc_macro2::Span::call_site(), msg));
+    }
+    Ok(batched)
+}
+
+/// Rewrites the `?`s in the body of a loop.
+struct Items<'a> {
+    message: &'a TokenStream,
+    ty: &'a syn::Type,
+    index: &'a syn::Ident,
+}
+
+impl VisitMut for Items<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        match expr {
+            // These have their own `?`s, which don't return from the function.
+            syn::Expr::Closure(_) | syn::Expr::Async(_) => {}
+            syn::Expr::Try(try_expr) => {
+                self.visit_expr_mut(&mut try_expr.expr);
+                let inner = &try_expr.expr;
+                let (message, ty, index) = (self.message, self.ty, self.index);
+                let failed = item_failed();
+                let value = quote::format_ident!("value", span = proc_macro2::Span::mixed_site());
+                let err = quote::format_ident!("err", span = proc_macro2::Span::mixed_site());
+                let result = quote::format_ident!("result", span = proc_macro2::Span::mixed_site());
+                *expr = syn::parse2(quote_spanned! {try_expr.question_token.span()=>
+                    match #inner {
+                        ::std::result::Result::Ok(#value) => #value,
+                        ::std::result::Result::Err(#err) => {
+                            let #result: #ty =
+                                ::std::result::Result::Err(::std::convert::From::from(#err));
+                            return ::context_attribute::__private::Contextable::apply_context(
+                                #result,
+                                || {
+                                    #failed = true;
+                                    ::context_attribute::__private::Message::Owned(
+                                        format!("{}: item {}", #message, #index)
+                                    )
+                                },
+                            );
+                        }
+                    }
+                })
+                .expect("the rewritten `?` is an expression");
+            }
+            expr => syn::visit_mut::visit_expr_mut(self, expr),
+        }
+    }
+
+    // Nested items have their own bodies.
+    fn visit_item_mut(&mut self, _: &mut syn::Item) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::batched;
+    use quote::quote;
+
+    #[test]
+    fn rewrite_question_marks_in_loops() {
+        let block: syn::Block = syn::parse_quote!({
+            let rows = load()?;
+            for row in rows {
+                let parse = |row: &str| Ok(row.parse()?);
+                save(parse(row)?)?;
+            }
+        });
+        let ty = syn::parse_quote!(Result<(), Error>);
+        let stmts = batched(&block.stmts, &quote!("Import"), &ty).unwrap();
+        let body = quote!(#(#stmts)*).to_string();
+        assert!(body.starts_with("let rows = load () ? ;"));
+        assert_eq!(body.matches("item_failed = true").count(), 2);
+        assert!(body.contains("Ok (row . parse () ?)"));
+    }
+
+    #[test]
+    fn require_a_loop() {
+        let block: syn::Block = syn::parse_quote!({ load()? });
+        let ty = syn::parse_quote!(Result<(), Error>);
+        let err = batched(&block.stmts, &quote!("Import"), &ty).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`batch` requires a `for` loop in the function body"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Recording contexts in the catalog.
+
+use crate::message::find_doc;
+use crate::options::Options;
+use crate::{CATALOG, CATALOG_VAR};
+use quote::ToTokens;
+
+/// Record a function's context in the catalog, if the `catalog` feature is enabled and
+/// `CONTEXT_ATTRIBUTE_CATALOG` is set.
+///
+/// The context is recorded as written, before interpolation, on a line of the form
+/// `file:line<TAB>function<TAB>context`. Tabs, newlines, and backslashes in it are escaped.
+pub(crate) fn catalog(input: &syn::ItemFn, prefix: &str, options: &Options) -> syn::Result<()> {
+    let path = match std::env::var_os(CATALOG_VAR) {
+        Some(path) if CATALOG => std::path::PathBuf::from(path),
+        _ => return Ok(()),
+    };
+    let context = match (
+        &options.code,
+        &options.translate,
+        &options.msg,
+        &options.with,
+    ) {
+        (Some(code), _, _, _) => crate::codes::lookup(code)?.message.value(),
+        (None, Some(key), _, _) => key.value(),
+        (None, None, Some(msg), _) => msg.to_token_stream().to_string(),
+        (None, None, None, Some(method)) => format!("{}()", method.to_token_stream()),
+        (None, None, None, None) => match find_doc(input, options)? {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit),
+                ..
+            }) => lit.value(),
+            doc => doc.to_token_stream().to_string(),
+        },
+    };
+    let ident = &input.sig.ident;
+    let location = format!(
+        "{}:{}",
+        proc_macro::Span::call_site().file(),
+        ident.span().unwrap().line()
+    );
+    let context = format!("{}{}", prefix, context)
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n");
+    let entry = format!("{}\t{}\t{}\n", location, ident, context);
+    append(&path, &entry).map_err(|err| {
+        let msg = format!(
+            "couldn't record the context in {} at `{}`: {}",
+            CATALOG_VAR,
+            path.display(),
+            err
+        );
+        syn::Error::new(ident.span(), msg)
+    })
+}
+
+/// Append an entry to a file, holding a lock on it so entries written by macro invocations that
+/// run in parallel don't interleave.
+fn append(path: &std::path::Path, entry: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.lock()?;
+    file.write_all(entry.as_bytes())?;
+    file.unlock()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn append_from_many_threads() {
+        let path = std::env::temp_dir().join(format!("catalog-{}.tsv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for entry in 0..50 {
+                        let line = format!("src/lib.rs:{}\tf{}\tDo the thing\n", entry, thread);
+                        super::append(&path, &line).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let catalog = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(catalog.lines().count(), 400);
+        assert!(catalog
+            .lines()
+            .all(|line| line.split('\t').count() == 3 && line.ends_with("Do the thing")));
+    }
+}
@@ -0,0 +1,473 @@
+//! The third stage: generating the function around the message.
+
+use crate::lint::{infallible, warning, INFALLIBLE};
+use crate::message::Message;
+use crate::options::Options;
+use crate::types::{
+    boxed_future_output, generic_param, impl_future_output, infer_impl_traits, result_err_type,
+    result_ok_type,
+};
+use crate::{error, CONTEXT_STACK, LINT_INFALLIBLE, STRICT};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// Generate a function that attaches the message to the result of its body.
+///
+/// Sync bodies are run in a closure, and `async fn` bodies in an `async` block, so `return` and
+/// `?` in them return from the body rather than the function. Bodies of functions returning a
+/// future, including `#[async_trait]` methods, are awaited in a new future instead.
+pub(crate) fn codegen(
+    input: &syn::ItemFn,
+    message: &Message<'_>,
+    options: &Options,
+) -> TokenStream {
+    let attrs = &input.attrs;
+    let vis = &input.vis;
+    let sig = &input.sig;
+    let result = quote::format_ident!("result", span = proc_macro2::Span::mixed_site());
+
+    let ty = match &sig.output {
+        syn::ReturnType::Type(_, ty) => ty,
+        syn::ReturnType::Default => {
+            unreachable!("functions without a return type are emitted unchanged")
+        }
+    };
+    let output = boxed_future_output(ty)
+        .or_else(|| impl_future_output(ty))
+        .unwrap_or(ty);
+    if let Some(err) = result_err_type(output) {
+        if let Some(param) = generic_param(err, &sig.generics) {
+            let msg = format!(
+                "#[context] can't attach a context to the generic error type `{}`, return a \
+                 backend's error type instead, as in `Result<T, failure::Error>`, and convert to \
+                 it with `?` or `.map_err(Into::into)`",
+                param
+            );
+            return error(err.span(), &msg);
+        }
+    }
+    // The result of the body is bound to the return type, though with its `impl Trait`s inferred.
+    let inferred = infer_impl_traits(ty);
+    let body = match options.batch {
+        true => match crate::batch::batched(&input.block.stmts, &message.text, &inferred) {
+            Ok(body) => body,
+            Err(err) => return err.to_compile_error(),
+        },
+        false => input.block.stmts.clone(),
+    };
+    let wrap = wrap(
+        &result,
+        &message.text,
+        &message.by_type,
+        &message.notes,
+        options,
+        ty.span(),
+    );
+    // With `err_if`, even a body that always returns `Ok` can fail.
+    let lint = match LINT_INFALLIBLE && options.err_if.is_none() && infallible(&input.block) {
+        true if STRICT => return error(sig.ident.span(), INFALLIBLE),
+        true => warning(sig.ident.span(), INFALLIBLE),
+        false => quote!(),
+    };
+
+    // `#[async_trait]` expands before us, and hands us a method returning a boxed future. Functions
+    // returning `impl Future` return one of their own. Await that future in a new one so its
+    // output gets the context instead.
+    let future_output = match boxed_future_output(ty) {
+        Some(output) => Some((output, true)),
+        None => impl_future_output(ty).map(|output| (output, false)),
+    };
+    if let (true, Some(_)) = (options.batch, future_output) {
+        return error(
+            sig.ident.span(),
+            "`batch` isn't supported on functions returning a future",
+        );
+    }
+    if options.errno && (future_output.is_some() || sig.asyncness.is_some()) {
+        return error(
+            sig.ident.span(),
+            "`errno` is only supported on functions that aren't async, since the error has to be \
+             read right after the C function returns",
+        );
+    }
+    if let Some((output, boxed)) = future_output {
+        if let (Some(variant), true) = (&options.variant, boxed) {
+            return error(
+                variant.span(),
+                "`variant` isn't supported on `#[async_trait]` methods",
+            );
+        }
+        let output = match body_type(&infer_impl_traits(output), options) {
+            Ok(output) => output,
+            Err(err) => return err.to_compile_error(),
+        };
+        let future = match &options.inner_name {
+            Some(name) => name.clone(),
+            None => quote::format_ident!("future", span = proc_macro2::Span::mixed_site()),
+        };
+        let scoped = scoped(quote!(#future), &message.text);
+        let wrapper = quote! {
+            async move {
+                let #result: #output = #scoped.await;
+                #wrap
+            }
+        };
+        let wrapper = match boxed {
+            true => quote!(::std::boxed::Box::pin(#wrapper)),
+            false => wrapper,
+        };
+        let wrapped = quote! {
+            #lint
+            let #future = { #(#body)* };
+            #wrapper
+        };
+        let block = debug_only(wrapped, &input.block.stmts, options);
+        return quote!(#(#attrs)* #vis #sig { #block });
+    }
+
+    let body_ty = match body_type(&inferred, options) {
+        Ok(body_ty) => body_ty,
+        Err(err) => return err.to_compile_error(),
+    };
+    // The closure is called as an `FnOnce`, and is spanned in this crate's edition, so it only
+    // captures the places the body uses. Together those let the body return borrows of `&mut`
+    // arguments, as in `fn first(v: &mut [u8]) -> Result<&mut u8, Error>`.
+    let inner = match sig.asyncness {
+        Some(_) => quote!(async { #(#body)* }),
+        None => quote_spanned!(proc_macro2::Span::mixed_site()=> || -> #body_ty { #(#body)* }),
+    };
+    let (bind, inner) = match &options.inner_name {
+        Some(name) => (quote!(let #name = #inner;), quote!(#name)),
+        None => (quote!(), inner),
+    };
+    let call = match sig.asyncness {
+        Some(_) => {
+            let scoped = scoped(inner, &message.text);
+            quote!({ #bind #scoped.await })
+        }
+        None => quote!({ #bind ::context_attribute::__private::call(#inner) }),
+    };
+    // With `errno`, the body's return code is turned into the function's result.
+    let (call, ty) = match options.errno {
+        true => (
+            quote!(::context_attribute::__private::errno(#call)),
+            quote!(#inferred),
+        ),
+        false => (call, body_ty),
+    };
+    let enter = match (CONTEXT_STACK, sig.asyncness) {
+        (true, None) => {
+            let guard = quote::format_ident!("_guard", span = proc_macro2::Span::mixed_site());
+            let text = &message.text;
+            quote!(let #guard = ::context_attribute::__private::enter(|| #text);)
+        }
+        _ => quote!(),
+    };
+
+    let batch = match options.batch {
+        true => {
+            let failed = crate::batch::item_failed();
+            quote!(let mut #failed = false;)
+        }
+        false => quote!(),
+    };
+    let wrapped = quote! {
+        #lint
+        #enter
+        #batch
+        let #result: #ty = #call;
+        #wrap
+    };
+    let block = debug_only(wrapped, &input.block.stmts, options);
+    quote!(#(#attrs)* #vis #sig { #block })
+}
+
+/// Only use the wrapped body in debug builds if `debug_only` is set, and the original one
+/// otherwise.
+///
+/// Both bodies go in the same function, so its signature is the same either way.
+fn debug_only(wrapped: TokenStream, body: &[syn::Stmt], options: &Options) -> TokenStream {
+    match options.debug_only {
+        true => quote! {
+            #[cfg(debug_assertions)]
+            {
+                #wrapped
+            }
+            #[cfg(not(debug_assertions))]
+            {
+                #(#body)*
+            }
+        },
+        false => wrapped,
+    }
+}
+
+/// Get the type to bind the result of the function body to.
+///
+/// With `variant`, the body's error type is inferred from the variant's source field instead. With
+/// `errno`, the body returns the `Ok` type, a C function's return code.
+fn body_type(ty: &syn::Type, options: &Options) -> syn::Result<TokenStream> {
+    if options.errno {
+        return match result_ok_type(ty) {
+            Some(ok) => Ok(quote!(#ok)),
+            None => Err(syn::Error::new(
+                ty.span(),
+                "`errno` requires a return type of the form `Result<T, E>`",
+            )),
+        };
+    }
+    match &options.variant {
+        Some(_) => match result_ok_type(ty) {
+            Some(ok) => Ok(quote!(::std::result::Result<#ok, _>)),
+            None => Err(syn::Error::new(
+                ty.span(),
+                "`variant` requires a return type of the form `Result<T, E>`",
+            )),
+        },
+        None => Ok(quote!(#ty)),
+    }
+}
+
+/// Push the message onto the context stack while a future is polled.
+///
+/// Without the `context-stack` feature the future is returned as-is.
+fn scoped(future: TokenStream, message: &TokenStream) -> TokenStream {
+    match CONTEXT_STACK {
+        true => quote!(::context_attribute::__private::scope(#future, || #message)),
+        false => future,
+    }
+}
+
+/// Attach the context message to the result of the function body.
+///
+/// Messages for specific error types are picked by walking the error's chain of causes, in order,
+/// and the default message is used if none of them match. Errors about return types that don't
+/// support a context point at `ty_span`.
+fn wrap(
+    result: &syn::Ident,
+    message: &TokenStream,
+    by_type: &[(&syn::Type, TokenStream)],
+    notes: &[TokenStream],
+    options: &Options,
+    ty_span: proc_macro2::Span,
+) -> TokenStream {
+    let contextable = quote_spanned!(ty_span=> ::context_attribute::__private::Contextable);
+    // With `on_ok`, the message closures record whether they ran, i.e. whether an error occurred.
+    let failed = quote::format_ident!("failed", span = proc_macro2::Span::mixed_site());
+    let set_failed = options.on_ok.as_ref().map(|_| quote!(#failed = true;));
+    let error_message = match set_failed {
+        Some(_) => quote!({ #set_failed #message }),
+        None => quote!(#message),
+    };
+    // With `send_to`, the message is sent before it's attached, without the `sep` and error.
+    let send = |message| match &options.send_to {
+        Some(sender) => quote!(::context_attribute::__private::send(&#sender, #message)),
+        None => message,
+    };
+    let error_message = send(error_message);
+    // With `err_if`, matching `Ok` values become errors before the context is attached to them.
+    let sentinel = options.err_if.as_ref().map(|(predicate, expr)| {
+        let value = quote_spanned!(predicate.span()=> result);
+        let sentinel = quote!(::context_attribute::__private::Sentinel(#predicate));
+        quote! {
+            let #result = match #result {
+                ::std::result::Result::Ok(#value) if #expr => {
+                    ::std::result::Result::Err(::std::convert::From::from(#sentinel))
+                }
+                #result => #result,
+            };
+        }
+    });
+    let mut arg = result.clone();
+    arg.set_span(result.span().located_at(ty_span));
+    let mut wrap = match (
+        &options.variant,
+        by_type.is_empty() && options.sep.is_none() && !options.os_error,
+    ) {
+        (Some(variant), _) => {
+            let source = quote::format_ident!("source", span = proc_macro2::Span::mixed_site());
+            let error_message = match &options.sep {
+                Some(sep) => quote! {
+                    ::context_attribute::__private::separated(#error_message, #sep, &#source)
+                },
+                None => error_message,
+            };
+            let error = match &options.variant_fields {
+                // A shorthand `context` field gets the message, and the other fields are set as
+                // written.
+                Some(fields) => {
+                    let fields = fields.iter().map(|field| match &field.member {
+                        syn::Member::Named(name)
+                            if field.colon_token.is_none() && name == "context" =>
+                        {
+                            quote!(#name: ::std::convert::Into::into(#error_message))
+                        }
+                        _ => quote!(#field),
+                    });
+                    quote!(#variant { #(#fields,)* source: #source })
+                }
+                None => quote!(#variant(::std::convert::Into::into(#error_message), #source)),
+            };
+            quote! {
+                let #result = #result.map_err(|#source| #error);
+            }
+        }
+        (None, true) => quote! {
+            let #result = #contextable::apply_context(#arg, || #error_message);
+        },
+        (None, false) => {
+            let err = quote::format_ident!("err", span = proc_macro2::Span::mixed_site());
+            let (tys, messages): (Vec<_>, Vec<_>) = by_type.iter().cloned().unzip();
+            let message = quote! {
+                #(if ::context_attribute::__private::Downcast::is_caused_by::<#tys>(#err) {
+                    #messages
+                } else)* {
+                    #message
+                }
+            };
+            let message = match options.os_error {
+                true => quote!(::context_attribute::__private::os_error(#message, #err)),
+                false => message,
+            };
+            let message = send(message);
+            let message = match &options.sep {
+                Some(sep) => {
+                    quote!(::context_attribute::__private::separated(#message, #sep, #err))
+                }
+                None => message,
+            };
+            quote! {
+                let #result = #contextable::apply_context_with(#arg, |#err| {
+                    #set_failed
+                    #message
+                });
+            }
+        }
+    };
+    // Errors from the items of a `batch` already have a context of their own.
+    if options.batch {
+        let item_failed = crate::batch::item_failed();
+        wrap = quote! {
+            let #result = match #item_failed {
+                true => #result,
+                false => {
+                    #wrap
+                    #result
+                }
+            };
+        };
+    }
+    if options.verbose {
+        wrap = quote! {
+            let #result = match ::context_attribute::__private::verbose() {
+                true => {
+                    #wrap
+                    #result
+                }
+                false => #result,
+            };
+        };
+    }
+    for note in notes {
+        wrap.extend(quote! {
+            let #result = ::context_attribute::__private::note(#result, || #note);
+        });
+    }
+    // The error is printed through a reference, so it's still returned whole, with its causes.
+    // With `or_else`, the error is swallowed, so it's printed to surface the context.
+    if options.eprintln || options.or_else.is_some() {
+        let level = match (&options.level, options.eprintln) {
+            (Some(level), _) => level.value(),
+            (None, true) => "error".to_string(),
+            (None, false) => "warn".to_string(),
+        };
+        wrap.extend(match options.variant {
+            Some(_) => quote! {
+                if let ::std::result::Result::Err(#result) = &#result {
+                    ::context_attribute::__private::eprint(#level, ::std::iter::once(#result));
+                }
+            },
+            None => quote!(::context_attribute::__private::Contextable::eprint(&#result, #level);),
+        });
+    }
+    if let Some(on_ok) = &options.on_ok {
+        wrap.extend(quote! {
+            if !#failed {
+                #on_ok(&#message);
+            }
+        });
+        wrap = quote!(let mut #failed = false; #wrap);
+    }
+    if let Some(fallback) = &options.or_else {
+        wrap.extend(quote! {
+            let #result = match #result {
+                ::std::result::Result::Err(_) => ::std::result::Result::Ok(#fallback),
+                #result => #result,
+            };
+        });
+    }
+    if let Some(sentinel) = sentinel {
+        wrap = quote!(#sentinel #wrap);
+    }
+    quote!(#wrap #result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::codegen;
+    use crate::message::build_message;
+    use crate::options::Options;
+    use quote::{quote, ToTokens};
+    use syn::parse::Parser;
+
+    fn expand(options: proc_macro2::TokenStream, input: &syn::ItemFn) -> syn::ItemFn {
+        let options = Options::parse.parse2(options).unwrap();
+        let message = build_message(input, &options).unwrap();
+        syn::parse2(codegen(input, &message, &options)).unwrap()
+    }
+
+    #[test]
+    fn keep_the_signature() {
+        let input: syn::ItemFn = syn::parse_quote! {
+            /// Load the config
+            pub fn load(path: &str) -> Result<Config, Error> {
+                parse(path)
+            }
+        };
+        let expanded = expand(quote!(), &input);
+        assert_eq!(expanded.sig, input.sig);
+        assert_eq!(expanded.vis, input.vis);
+        assert_eq!(expanded.attrs, input.attrs);
+        let body = expanded.block.to_token_stream().to_string();
+        assert!(body.contains("__private :: call"));
+        assert!(body.contains("apply_context"));
+    }
+
+    #[test]
+    fn await_async_bodies() {
+        let input: syn::ItemFn = syn::parse_quote! {
+            /// Load the config
+            async fn load() -> Result<Config, Error> {
+                parse().await
+            }
+        };
+        let expanded = expand(quote!(), &input);
+        let body = expanded.block.to_token_stream().to_string();
+        assert!(body.contains(". await"));
+        assert!(!body.contains("__private :: call"));
+    }
+
+    #[test]
+    fn wrap_returned_futures() {
+        let input: syn::ItemFn = syn::parse_quote! {
+            /// Load the config
+            fn load() -> impl Future<Output = Result<Config, Error>> {
+                async { parse() }
+            }
+        };
+        let expanded = expand(quote!(), &input);
+        let body = expanded.block.to_token_stream().to_string();
+        assert!(body.contains("async move"));
+    }
+}
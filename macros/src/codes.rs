@@ -0,0 +1,84 @@
+//! Looking messages up in the table of error codes.
+
+use crate::CODES_VAR;
+
+/// An entry of the table of error codes.
+pub(crate) struct Entry {
+    /// The message for the code, as written in the table.
+    pub(crate) message: syn::LitStr,
+    /// The path of the table, so the function can be recompiled when it changes.
+    pub(crate) table: String,
+}
+
+/// Look a code up in the table in the file `CONTEXT_ATTRIBUTE_CODES` points to.
+///
+/// Each line of the table is a code followed by whitespace and its message, as in `E1001<TAB>Read
+/// the config`. Blank lines and lines starting with `#` are skipped. A relative path is resolved
+/// against the directory of the crate being built.
+pub(crate) fn lookup(code: &syn::LitStr) -> syn::Result<Entry> {
+    let path = match std::env::var_os(CODES_VAR) {
+        Some(path) => std::path::PathBuf::from(path),
+        None => {
+            let msg = format!(
+                "`catalog` requires {} to be set to the path of a table of error codes",
+                CODES_VAR
+            );
+            return Err(syn::Error::new(code.span(), msg));
+        }
+    };
+    let path = match (path.is_relative(), std::env::var_os("CARGO_MANIFEST_DIR")) {
+        (true, Some(dir)) => std::path::Path::new(&dir).join(path),
+        _ => path,
+    };
+    let table = std::fs::read_to_string(&path).map_err(|err| {
+        let msg = format!(
+            "couldn't read the error codes in {} at `{}`: {}",
+            CODES_VAR,
+            path.display(),
+            err
+        );
+        syn::Error::new(code.span(), msg)
+    })?;
+    let message = find(&table, &code.value()).ok_or_else(|| {
+        let msg = format!(
+            "error code `{}` isn't in the table at `{}`",
+            code.value(),
+            path.display()
+        );
+        syn::Error::new(code.span(), msg)
+    })?;
+    Ok(Entry {
+        message: syn::LitStr::new(message, code.span()),
+        table: path.display().to_string(),
+    })
+}
+
+/// Find the message for a code in a table.
+fn find<'a>(table: &'a str, code: &str) -> Option<&'a str> {
+    table
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .find_map(|line| {
+            let (key, message) = line.split_once(char::is_whitespace)?;
+            match key == code {
+                true => Some(message.trim()),
+                false => None,
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::find;
+
+    #[test]
+    fn find_codes_in_a_table() {
+        let table = "# Storage\nE1001\tRead the config\n\nE1002   Write {path}\nE1003\n";
+        assert_eq!(find(table, "E1001"), Some("Read the config"));
+        assert_eq!(find(table, "E1002"), Some("Write {path}"));
+        assert_eq!(find(table, "E1003"), None);
+        assert_eq!(find(table, "# Storage"), None);
+        assert_eq!(find(table, "E1004"), None);
+    }
+}
@@ -0,0 +1,244 @@
+//! Procedural macros for [`context-attribute`].
+//!
+//! This crate is an implementation detail, depend on [`context-attribute`] instead.
+//!
+//! [`context-attribute`]: https://docs.rs/context-attribute
+//!
+//! # Architecture
+//!
+//! `#[context]` expands a function in three stages, each in its own module:
+//!
+//! 1. `options` parses the attribute's arguments into `Options`, and rejects invalid combinations
+//!    of them.
+//! 2. `message` builds a `Message` from the options and the function: the expression for the
+//!    context, with its prefix and suffixes, and those for the `map` and `notes` messages.
+//! 3. `codegen` generates the function, wrapping its body so the message is attached to its
+//!    result.
+//!
+//! Each stage only depends on the output of the ones before it, so new options usually only touch
+//! one of them. A new source for the message, like `msg` or `t`, goes in `build_message`. A new
+//! suffix goes in `suffixed`, so it's added the same way for every source. A new way of handling
+//! the result goes in `wrap`, which is shared by sync functions, `async fn`s, and functions
+//! returning futures. Traits and impl blocks are expanded by running each of their methods through
+//! the same stages.
+//!
+//! The `lint`, `batch`, `catalog`, `codes`, and `types` modules hold the checks on annotated
+//! functions, the rewriting of loops for `batch`, the error catalog, the table of error codes, and
+//! the helpers that recognize return types, respectively.
+
+#![forbid(unsafe_code, future_incompatible, rust_2018_idioms)]
+#![deny(missing_debug_implementations, nonstandard_style)]
+#![warn(missing_docs)]
+#![cfg_attr(test, deny(warnings))]
+#![recursion_limit = "512"]
+
+mod batch;
+mod catalog;
+mod codegen;
+mod codes;
+mod lint;
+mod message;
+mod options;
+mod types;
+
+use options::Options;
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// Whether the `strict` feature is enabled.
+const STRICT: bool = cfg!(feature = "strict");
+
+/// Whether the `eyre` feature is enabled.
+const EYRE: bool = cfg!(feature = "eyre");
+
+/// Whether the `json` feature is enabled.
+const JSON: bool = cfg!(feature = "json");
+
+/// Whether the `context-stack` feature is enabled.
+const CONTEXT_STACK: bool = cfg!(feature = "context-stack");
+
+/// Whether the `lint-infallible` feature is enabled.
+const LINT_INFALLIBLE: bool = cfg!(feature = "lint-infallible");
+
+/// Whether the `tokio` feature is enabled.
+const TOKIO: bool = cfg!(feature = "tokio");
+
+/// Whether the `catalog` feature is enabled.
+const CATALOG: bool = cfg!(feature = "catalog");
+
+/// The environment variable with the comma-separated list of allowed `prefix`es.
+const PREFIXES_VAR: &str = "CONTEXT_ATTRIBUTE_PREFIXES";
+
+/// The environment variable with the path of the file to record each context in.
+const CATALOG_VAR: &str = "CONTEXT_ATTRIBUTE_CATALOG";
+
+/// The environment variable with the path of the table of error codes that `catalog` looks up.
+const CODES_VAR: &str = "CONTEXT_ATTRIBUTE_CODES";
+
+/// Use a doc comment to annotate the failure context of a function.
+#[proc_macro_attribute]
+pub fn context(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = syn::parse_macro_input!(attr with Options::parse);
+    let expanded = match syn::parse_macro_input!(item as syn::Item) {
+        // A skipped item is kept as-is, and only marks it for an annotated trait or impl block.
+        item if options.skip => quote!(#item),
+        syn::Item::Fn(input) => context_fn(&options, input),
+        syn::Item::Trait(input) => context_trait(&options, input),
+        syn::Item::Impl(input) => context_impl(&options, input),
+        // Keep the item, so its uses don't cause errors of their own.
+        item => {
+            let msg = "#[context] can only be applied to functions, traits, and impl blocks";
+            let err = syn::Error::new_spanned(&item, msg).to_compile_error();
+            quote!(#item #err)
+        }
+    };
+    expanded.into()
+}
+
+/// Apply the context to each default method of a trait that returns a value.
+///
+/// Required methods, and methods that have a `#[context]` of their own, are left as-is.
+fn context_trait(options: &Options, mut input: syn::ItemTrait) -> proc_macro2::TokenStream {
+    let options = &Options {
+        owner: Some(input.ident.to_string()),
+        ..options.clone()
+    };
+    let mut errors = vec![];
+    for item in &mut input.items {
+        if let syn::TraitItem::Fn(method) = item {
+            if let Some(block) = &mut method.default {
+                context_method(options, &method.attrs, &method.sig, block, &mut errors);
+            }
+        }
+    }
+    quote!(#input #(#errors)*)
+}
+
+/// Apply the context to each method of an impl block that returns a value.
+///
+/// Methods that have a `#[context]` of their own, including `#[context(skip)]`, are left as-is, as
+/// are items generated by macro invocations in the block, since they aren't expanded yet.
+fn context_impl(options: &Options, mut input: syn::ItemImpl) -> proc_macro2::TokenStream {
+    let self_ty = &input.self_ty;
+    let owner = match &input.trait_ {
+        Some((_, path, _)) => quote!(<#self_ty as #path>),
+        None => quote!(#self_ty),
+    };
+    let options = &Options {
+        owner: Some(owner.to_string().replace(' ', "")),
+        ..options.clone()
+    };
+    let mut errors = vec![];
+    for item in &mut input.items {
+        if let syn::ImplItem::Fn(method) = item {
+            context_method(
+                options,
+                &method.attrs,
+                &method.sig,
+                &mut method.block,
+                &mut errors,
+            );
+        }
+    }
+    quote!(#input #(#errors)*)
+}
+
+/// Apply the context to the body of a method in an annotated trait or impl block, or collect the
+/// errors from doing so.
+fn context_method(
+    options: &Options,
+    attrs: &[syn::Attribute],
+    sig: &syn::Signature,
+    block: &mut syn::Block,
+    errors: &mut Vec<proc_macro2::TokenStream>,
+) {
+    // Like for functions, whether the return type can carry a context is left to `Contextable`,
+    // so aliases like `Fallible<T>` and `#[async_trait]`'s boxed futures are wrapped too.
+    let returns = matches!(sig.output, syn::ReturnType::Type(..));
+    let annotated = attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|seg| seg.ident == "context")
+    });
+    if !returns || annotated {
+        return;
+    }
+    let input = syn::ItemFn {
+        attrs: attrs.to_vec(),
+        vis: syn::Visibility::Inherited,
+        sig: sig.clone(),
+        block: Box::new(block.clone()),
+    };
+    let expanded = context_fn(options, input);
+    match syn::parse2::<syn::ItemFn>(expanded.clone()) {
+        Ok(expanded) => *block = *expanded.block,
+        Err(_) => errors.push(expanded),
+    }
+}
+
+/// Apply the context to a function.
+///
+/// Functions the context can't apply to are emitted unchanged, and the others go through the
+/// `message` and `codegen` stages.
+fn context_fn(options: &Options, input: syn::ItemFn) -> proc_macro2::TokenStream {
+    // Whether the return type can carry a context is left to the `Contextable` trait, so type
+    // aliases and wrappers work without having to recognize them here.
+    if let syn::ReturnType::Default = input.sig.output {
+        match STRICT {
+            true => {
+                return error(
+                    input.sig.span(),
+                    "#[context] requires a function returning a Result",
+                )
+            }
+            false => return quote!(#input),
+        }
+    }
+    let documented = !message::docs(&input.attrs).is_empty();
+    let explicit = options.translate.is_some()
+        || options.code.is_some()
+        || options.msg.is_some()
+        || options.with.is_some()
+        || options.default.is_some();
+    // Strict mode only requires a doc comment on functions that can fail, but then requires it
+    // even with `optional_doc`, so every fallible function is documented.
+    if !documented && !explicit {
+        let infallible = options.err_if.is_none() && lint::infallible(&input.block);
+        match (STRICT, infallible) {
+            (true, true) if !LINT_INFALLIBLE => return quote!(#input),
+            (true, false) if options.optional_doc => {
+                return error(
+                    input.sig.ident.span(),
+                    "no doc comment provided, strict mode requires one on functions whose body \
+                     can return an error, even with `optional_doc`",
+                )
+            }
+            (false, _) if options.optional_doc => return quote!(#input),
+            _ => {}
+        }
+    }
+
+    match message::build_message(&input, options) {
+        Ok(message) => codegen::codegen(&input, &message, options),
+        Err(err) => err.to_compile_error(),
+    }
+}
+
+/// Check that every public function returning a `Result` in a module has `#[context]`.
+#[proc_macro]
+pub fn require_context(item: TokenStream) -> TokenStream {
+    let module = syn::parse_macro_input!(item as syn::ItemMod);
+    let mut errors = vec![];
+    lint::missing_context(&module, &mut errors);
+    let errors = errors.into_iter().map(|err| err.to_compile_error());
+    quote!(#module #(#errors)*).into()
+}
+
+/// Emit a compile error at the given span.
+fn error(span: proc_macro2::Span, msg: &str) -> proc_macro2::TokenStream {
+    quote_spanned! {
+        span => compile_error!(#msg);
+    }
+}
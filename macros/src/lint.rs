@@ -0,0 +1,144 @@
+//! Checks on annotated functions, and on modules for `require_context!`.
+
+use crate::types::last_segment_is;
+use quote::quote_spanned;
+
+/// Collect an error for every public function returning a `Result` in a module, its submodules,
+/// and its impl blocks, that doesn't have `#[context]`, or isn't in an impl block that has it.
+pub(crate) fn missing_context(module: &syn::ItemMod, errors: &mut Vec<syn::Error>) {
+    let check = |attrs: &[syn::Attribute],
+                 vis: &syn::Visibility,
+                 sig: &syn::Signature,
+                 errors: &mut Vec<_>| {
+        let public = matches!(vis, syn::Visibility::Public(_));
+        let result = match &sig.output {
+            syn::ReturnType::Type(_, ty) => last_segment_is(ty, "Result"),
+            syn::ReturnType::Default => false,
+        };
+        if public && result && !has_context(attrs) {
+            let msg = format!(
+                "`{}` returns a Result but doesn't have #[context]",
+                sig.ident
+            );
+            errors.push(syn::Error::new(sig.ident.span(), msg));
+        }
+    };
+
+    let items = module.content.iter().flat_map(|(_, items)| items);
+    for item in items {
+        match item {
+            syn::Item::Fn(item) => check(&item.attrs, &item.vis, &item.sig, errors),
+            // An annotated impl block applies the context to its methods itself.
+            syn::Item::Impl(item) if has_context(&item.attrs) => {}
+            syn::Item::Impl(item) => {
+                for item in &item.items {
+                    if let syn::ImplItem::Fn(item) = item {
+                        check(&item.attrs, &item.vis, &item.sig, errors);
+                    }
+                }
+            }
+            syn::Item::Mod(item) => missing_context(item, errors),
+            _ => {}
+        }
+    }
+}
+
+/// Whether an item has a `#[context]` attribute.
+fn has_context(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|seg| seg.ident == "context")
+    })
+}
+
+/// The message for functions whose body can't return an error.
+pub(crate) const INFALLIBLE: &str =
+    "#[context] has no effect, the function body can't return an error";
+
+/// Check whether a function body trivially can't return an error.
+///
+/// This is conservative: a body is only infallible if it doesn't use `?`, `Err`, `Break`, or
+/// macros that might expand to any of them, and every value it returns is an `Ok(...)`.
+pub(crate) fn infallible(block: &syn::Block) -> bool {
+    struct Fallible(bool);
+
+    impl<'ast> syn::visit::Visit<'ast> for Fallible {
+        fn visit_expr_try(&mut self, _: &'ast syn::ExprTry) {
+            self.0 = true;
+        }
+
+        fn visit_expr_return(&mut self, ret: &'ast syn::ExprReturn) {
+            match &ret.expr {
+                Some(expr) if is_ok(expr) => syn::visit::visit_expr_return(self, ret),
+                _ => self.0 = true,
+            }
+        }
+
+        fn visit_path(&mut self, path: &'ast syn::Path) {
+            // `ControlFlow::Break` is the error path of functions returning a `ControlFlow`.
+            if path
+                .segments
+                .last()
+                .is_some_and(|seg| seg.ident == "Err" || seg.ident == "Break")
+            {
+                self.0 = true;
+            }
+        }
+
+        fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+            let known = [
+                "assert",
+                "assert_eq",
+                "debug_assert",
+                "eprintln",
+                "format",
+                "println",
+                "vec",
+                "write",
+            ];
+            if !known.iter().any(|name| mac.path.is_ident(name)) {
+                self.0 = true;
+            }
+        }
+
+        // Nested items have their own return type.
+        fn visit_item(&mut self, _: &'ast syn::Item) {}
+    }
+
+    fn is_ok(expr: &syn::Expr) -> bool {
+        match expr {
+            syn::Expr::Call(call) => match &*call.func {
+                syn::Expr::Path(path) => path
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|seg| seg.ident == "Ok"),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    let mut fallible = Fallible(false);
+    syn::visit::Visit::visit_block(&mut fallible, block);
+    match block.stmts.last() {
+        Some(syn::Stmt::Expr(expr, None)) => !fallible.0 && is_ok(expr),
+        Some(syn::Stmt::Expr(syn::Expr::Return(_), Some(_))) => !fallible.0,
+        _ => false,
+    }
+}
+
+/// Emit a warning at the given span.
+///
+/// Proc macros can't emit warnings on stable, so this uses a deprecated item instead.
+pub(crate) fn warning(span: proc_macro2::Span, msg: &str) -> proc_macro2::TokenStream {
+    let name = quote::format_ident!("context_has_no_effect", span = span);
+    quote_spanned! {span=>
+        #[deprecated(note = #msg)]
+        #[allow(non_camel_case_types)]
+        struct #name;
+        let _ = #name;
+    }
+}
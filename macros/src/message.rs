@@ -0,0 +1,814 @@
+//! The second stage: building the message that is attached to errors.
+
+use crate::options::Options;
+use crate::STRICT;
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+/// The messages for a function, ready to be attached to its errors.
+#[derive(Debug)]
+pub(crate) struct Message<'a> {
+    /// The expression for the context, evaluating to a `Message`, or to a `String` with `json`.
+    pub(crate) text: TokenStream,
+    /// The expressions for the `map` messages, along with the error types they're for.
+    pub(crate) by_type: Vec<(&'a syn::Type, TokenStream)>,
+    /// The expressions for the `notes` and `note` messages, in order.
+    pub(crate) notes: Vec<TokenStream>,
+}
+
+/// Build the messages for a function.
+///
+/// The context comes from the first of `catalog`, `t`, `msg`, `with`, and the doc comment that's
+/// set, or from `debug_msg` in debug builds. It's prefixed with the `id`, the `catalog` code, and
+/// the `prefix`, has the suffixes of [`suffixed`] appended, and is then wrapped in JSON with
+/// `json`. The `map` messages are prefixed the same way, but don't have suffixes.
+pub(crate) fn build_message<'a>(
+    input: &syn::ItemFn,
+    options: &'a Options,
+) -> syn::Result<Message<'a>> {
+    let mut prefix = match options.id {
+        true => format!("[E#{}] ", error_id(input, options)?),
+        false => String::new(),
+    };
+    if let Some(code) = &options.code {
+        prefix.push_str(&format!("[{}] ", code.value()));
+    }
+    if let Some(category) = &options.prefix {
+        prefix.push_str(&format!("{}: ", category.value()));
+    }
+    // With `max_len`, only the message itself is truncated, and the prefix is added after.
+    let body_prefix = match options.max_len {
+        Some(_) => "",
+        None => prefix.as_str(),
+    };
+    let by_type = options.map.iter().map(|(ty, msg)| {
+        let msg = syn::Expr::Lit(syn::ExprLit {
+            attrs: vec![],
+            lit: syn::Lit::Str(msg.clone()),
+        });
+        Ok((
+            ty,
+            interpolate(&msg, body_prefix, input.sig.receiver().is_some(), options)?,
+        ))
+    });
+    let by_type =
+        by_type.map(|message| message.map(|(ty, msg)| (ty, truncated(msg, &prefix, options))));
+    let by_type = by_type.collect::<syn::Result<Vec<_>>>()?;
+    let notes = match options.notes {
+        true => paragraphs(input, options).into_iter().skip(1).collect(),
+        false => vec![],
+    };
+    let notes = notes.into_iter().chain(options.note.clone()).map(|note| {
+        let note = syn::Expr::Lit(syn::ExprLit {
+            attrs: vec![],
+            lit: syn::Lit::Str(note),
+        });
+        interpolate(&note, "", input.sig.receiver().is_some(), options)
+    });
+    let notes = notes.collect::<syn::Result<Vec<_>>>()?;
+    let message = match (
+        &options.code,
+        &options.translate,
+        &options.msg,
+        &options.with,
+    ) {
+        (Some(code), _, _, _) => coded(code, body_prefix, input, options),
+        (None, Some(key), _, _) => Ok(translated(key, &options.translate_fn, body_prefix)),
+        (None, None, Some(msg), _) => expression(msg, body_prefix, input, options),
+        (None, None, None, Some(method)) => method_call(method, body_prefix, input),
+        (None, None, None, None) => find_doc(input, options).and_then(|doc| {
+            interpolate(&doc, body_prefix, input.sig.receiver().is_some(), options)
+        }),
+    };
+    // Both messages are compiled, so both are checked whichever profile is built.
+    let message = match &options.debug_msg {
+        Some(debug) => {
+            let debug = expression(debug, body_prefix, input, options)?;
+            let release = message?;
+            Ok(quote!(match cfg!(debug_assertions) {
+                true => #debug,
+                false => #release,
+            }))
+        }
+        None => message,
+    };
+    let message = suffixed(truncated(message?, &prefix, options), &input.sig, options);
+    crate::catalog::catalog(input, &prefix, options)?;
+    let message = match options.json {
+        true => json(message, &input.sig),
+        false => message,
+    };
+
+    Ok(Message {
+        text: message,
+        by_type,
+        notes,
+    })
+}
+
+/// Append the suffixes that options add to a message, in a fixed order: the attempt, the
+/// arguments, the location, the thread, and then the correlation id, as in `Read {path} (attempt
+/// 2) (path = "a.txt") (at src/lib.rs:10) (on thread main) (correlation id req-1)`.
+fn suffixed(message: TokenStream, sig: &syn::Signature, options: &Options) -> TokenStream {
+    let mut message = message;
+    if let Some(attempt) = &options.attempt {
+        message = quote_spanned! {attempt.span()=>
+            ::context_attribute::__private::Message::Owned(
+                format!("{} (attempt {})", #message, #attempt)
+            )
+        };
+    }
+    if options.args {
+        let args = arg_idents(sig);
+        let fmt = args
+            .iter()
+            .map(|arg| format!("{} = {{:?}}", arg))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let fmt = format!("{{}} ({})", fmt);
+        message = quote! {
+            ::context_attribute::__private::Message::Owned(format!(#fmt, #message, #(#args),*))
+        };
+    }
+    if options.location {
+        let line = quote_spanned!(sig.ident.span()=> line!());
+        message = quote! {
+            ::context_attribute::__private::Message::Owned(
+                format!("{} (at {}:{})", #message, file!(), #line)
+            )
+        };
+    }
+    if options.thread {
+        message = quote!(::context_attribute::__private::thread(#message));
+    }
+    if let Some(id) = &options.correlation_id {
+        message = quote_spanned! {id.span()=>
+            ::context_attribute::__private::correlated(#message, #id())
+        };
+    }
+    message
+}
+
+/// Truncate a message to `max_len` characters, if it's set, and then prefix it.
+///
+/// The message is built without the prefix when `max_len` is set, so the prefix isn't cut off.
+/// String literals are truncated and prefixed when expanding, and other messages when they're
+/// built.
+fn truncated(message: TokenStream, prefix: &str, options: &Options) -> TokenStream {
+    let max = match options.max_len {
+        Some(max) => max,
+        None => return message,
+    };
+    if let Some(text) = borrowed_literal(&message) {
+        let value = text.value();
+        let value = match value.chars().nth(max) {
+            Some(_) => value.chars().take(max - 1).chain(Some('…')).collect(),
+            None => value,
+        };
+        let text = syn::LitStr::new(&format!("{}{}", prefix, value), text.span());
+        return quote!(::context_attribute::__private::Message::Borrowed(#text));
+    }
+    let message = quote!(::context_attribute::__private::truncate(#message, #max));
+    match prefix.is_empty() {
+        true => message,
+        false => {
+            let fmt = format!("{}{{}}", escape(prefix));
+            quote!(::context_attribute::__private::Message::Owned(
+                format!(#fmt, #message)
+            ))
+        }
+    }
+}
+
+/// Get the string literal of a `Message::Borrowed("...")`.
+fn borrowed_literal(message: &TokenStream) -> Option<syn::LitStr> {
+    let call = match syn::parse2::<syn::Expr>(message.clone()) {
+        Ok(syn::Expr::Call(call)) if call.args.len() == 1 => call,
+        _ => return None,
+    };
+    let borrowed = match &*call.func {
+        syn::Expr::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|seg| seg.ident == "Borrowed"),
+        _ => false,
+    };
+    match (borrowed, call.args.first()) {
+        (
+            true,
+            Some(syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(text),
+                ..
+            })),
+        ) => Some(text.clone()),
+        _ => None,
+    }
+}
+
+/// Wrap a message into a JSON object, along with the function's arguments and location.
+fn json(message: TokenStream, sig: &syn::Signature) -> TokenStream {
+    let args = arg_idents(sig);
+    let names = args.iter().map(|arg| arg.to_string());
+    quote! {
+        ::context_attribute::__private::json(
+            #message,
+            &[#((#names, &#args as &dyn ::std::fmt::Debug)),*],
+            concat!(file!(), ":", line!()),
+        )
+    }
+}
+
+/// Get the identifiers the function's arguments are bound to, skipping destructuring patterns.
+fn arg_idents(sig: &syn::Signature) -> Vec<&syn::Ident> {
+    sig.inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            syn::FnArg::Typed(arg) => match &*arg.pat {
+                syn::Pat::Ident(pat) => Some(&pat.ident),
+                _ => None,
+            },
+            syn::FnArg::Receiver(_) => None,
+        })
+        .collect()
+}
+
+/// Get the values of the doc attributes of a function, in order.
+///
+/// The compiler expands `#[cfg_attr(...)]` on an item before handing it to the macro, but not on
+/// the items nested in it, like the methods of an annotated trait. The docs in those are only
+/// included if their cfg is trivially true, like `all()`, since other cfgs depend on the build and
+/// can't be evaluated here.
+pub(crate) fn docs(attrs: &[syn::Attribute]) -> Vec<syn::Expr> {
+    let mut docs = vec![];
+    for attr in attrs {
+        collect_docs(&attr.meta, true, &mut docs);
+    }
+    docs
+}
+
+/// Check whether any of the doc attributes of a function are in a `#[cfg_attr(...)]` whose cfg
+/// isn't trivially true.
+fn conditional_docs(attrs: &[syn::Attribute]) -> bool {
+    let mut docs = vec![];
+    for attr in attrs {
+        collect_docs(&attr.meta, false, &mut docs);
+    }
+    !docs.is_empty()
+}
+
+/// Collect the doc attributes in an attribute, looking into `#[cfg_attr(...)]`s.
+///
+/// With `known`, the docs in `cfg_attr`s whose cfg is trivially true are collected, and otherwise
+/// those in `cfg_attr`s whose cfg can't be evaluated are.
+fn collect_docs(meta: &syn::Meta, known: bool, docs: &mut Vec<syn::Expr>) {
+    match meta {
+        syn::Meta::NameValue(doc) if doc.path.is_ident("doc") && known => {
+            docs.push(doc.value.clone())
+        }
+        syn::Meta::List(list) if list.path.is_ident("cfg_attr") => {
+            let args = match list.parse_args_with(
+                syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated,
+            ) {
+                Ok(args) => args,
+                Err(_) => return,
+            };
+            let mut args = args.iter();
+            let cfg = args.next().and_then(cfg_value);
+            for meta in args {
+                match (cfg, known) {
+                    (Some(true), known) => collect_docs(meta, known, docs),
+                    (None, false) => collect_docs(meta, true, docs),
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Evaluate a cfg predicate that's made of only `all`, `any`, and `not`.
+///
+/// Returns `None` if the value depends on an option like `feature = "..."` or `unix`.
+fn cfg_value(cfg: &syn::Meta) -> Option<bool> {
+    let list = match cfg {
+        syn::Meta::List(list) => list,
+        _ => return None,
+    };
+    let args = list
+        .parse_args_with(syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+        .ok()?;
+    let values: Vec<_> = args.iter().map(cfg_value).collect();
+    if list.path.is_ident("all") {
+        match values.contains(&Some(false)) {
+            true => Some(false),
+            false => values.iter().all(Option::is_some).then_some(true),
+        }
+    } else if list.path.is_ident("any") {
+        match values.contains(&Some(true)) {
+            true => Some(true),
+            false => values.iter().all(Option::is_some).then_some(false),
+        }
+    } else if list.path.is_ident("not") && values.len() == 1 {
+        values[0].map(|value| !value)
+    } else {
+        None
+    }
+}
+
+/// Find the doc comment of a function.
+///
+/// The lines of a multi-line doc comment are joined with the `join` separator, and unless
+/// `no_trim` is set, the single leading space `///` adds is stripped from each of them. With
+/// `summary`, only the first sentence is kept. If any of the lines isn't a string literal, as with
+/// `#[doc = include_str!(...)]`, only the first line is used, and it's trimmed when the message is
+/// built instead.
+///
+/// If the function has no doc comment, or an empty one, the `default` message is used instead.
+pub(crate) fn find_doc(input: &syn::ItemFn, options: &Options) -> syn::Result<syn::Expr> {
+    let docs = docs(&input.attrs);
+    let first = match (docs.first(), &options.default) {
+        (Some(first), _) => first,
+        (None, Some(default)) => return Ok(syn::parse_quote!(#default)),
+        (None, None) if conditional_docs(&input.attrs) => {
+            let msg = "no doc comment provided, the one in `cfg_attr` can't be read before its \
+                       cfg is expanded, put #[context] on the method itself instead";
+            return Err(syn::Error::new(input.span(), msg));
+        }
+        (None, None) => return Err(syn::Error::new(input.span(), "no doc comment provided")),
+    };
+    let lines = docs
+        .iter()
+        .map(|doc| match doc {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit),
+                ..
+            }) => Some(lit.value()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let lines = match lines {
+        Some(lines) => lines,
+        None => return Ok(first.clone()),
+    };
+
+    let lines = lines.iter().map(|line| trim(line, options));
+    let lines: Vec<_> = match options.notes {
+        true => lines
+            .skip_while(|line| line.trim().is_empty())
+            .take_while(|line| !line.trim().is_empty())
+            .collect(),
+        false => lines.collect(),
+    };
+    let mut text = lines.join(&separator(options));
+    if options.summary {
+        text.truncate(summary_len(&text));
+    }
+    if text.trim().is_empty() {
+        if let Some(default) = &options.default {
+            return Ok(syn::parse_quote!(#default));
+        }
+        if STRICT {
+            return Err(syn::Error::new(
+                first.span(),
+                "#[context] requires a non-empty doc comment",
+            ));
+        }
+        let name = input.sig.ident.to_string();
+        return Ok(syn::parse_quote!(#name));
+    }
+    let lit = syn::LitStr::new(&text, first.span());
+    Ok(syn::parse_quote!(#lit))
+}
+
+/// Split the doc comment of a function into paragraphs, separated by blank lines.
+///
+/// Doc comments that aren't all literals have no paragraphs.
+fn paragraphs(input: &syn::ItemFn, options: &Options) -> Vec<syn::LitStr> {
+    let docs = docs(&input.attrs)
+        .into_iter()
+        .map(|doc| match doc {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit),
+                ..
+            }) => Some(lit),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let docs = match docs {
+        Some(docs) => docs,
+        None => return vec![],
+    };
+    let mut paragraphs = vec![];
+    let mut lines = vec![];
+    // A trailing blank line ends the last paragraph.
+    for lit in docs.into_iter().map(Some).chain(Some(None)) {
+        let line = lit.as_ref().map(syn::LitStr::value).unwrap_or_default();
+        match (trim(&line, options), lit) {
+            (line, Some(lit)) if !line.trim().is_empty() => lines.push((line.to_string(), lit)),
+            _ => {
+                if let Some((_, first)) = lines.first() {
+                    let text: Vec<_> = lines.iter().map(|(line, _)| line.as_str()).collect();
+                    paragraphs.push(syn::LitStr::new(
+                        &text.join(&separator(options)),
+                        first.span(),
+                    ));
+                }
+                lines.clear();
+            }
+        }
+    }
+    paragraphs
+}
+
+/// Strip the leading space `///` adds to a line of a doc comment, unless `no_trim` is set.
+fn trim<'a>(line: &'a str, options: &Options) -> &'a str {
+    match options.no_trim {
+        true => line,
+        false => line.strip_prefix(' ').unwrap_or(line),
+    }
+}
+
+/// The separator to join the lines of a doc comment with.
+fn separator(options: &Options) -> String {
+    options
+        .join
+        .as_ref()
+        .map_or_else(|| " ".to_string(), syn::LitStr::value)
+}
+
+/// Get the length of the first sentence of a text, up to and including the first `.` that's
+/// followed by whitespace.
+fn summary_len(text: &str) -> usize {
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '.' && chars.peek().is_some_and(|(_, next)| next.is_whitespace()) {
+            return i + 1;
+        }
+    }
+    text.len()
+}
+
+/// Build the message for an error code from its entry in the table of error codes.
+///
+/// The message is interpolated like a doc comment. The table is included in the expansion, so
+/// Cargo recompiles the function when it changes.
+fn coded(
+    code: &syn::LitStr,
+    prefix: &str,
+    input: &syn::ItemFn,
+    options: &Options,
+) -> syn::Result<TokenStream> {
+    let entry = crate::codes::lookup(code)?;
+    let table = entry.table;
+    let message = syn::Expr::Lit(syn::ExprLit {
+        attrs: vec![],
+        lit: syn::Lit::Str(entry.message),
+    });
+    let message = interpolate(&message, prefix, input.sig.receiver().is_some(), options)?;
+    Ok(quote!({
+        const _: &str = include_str!(#table);
+        #message
+    }))
+}
+
+/// Build the expression that looks up a translated context message.
+///
+/// The translation function is called with the key at the error site, and can return anything that
+/// converts into a `Cow<'static, str>`.
+fn translated(key: &syn::LitStr, translate_fn: &Option<syn::Path>, prefix: &str) -> TokenStream {
+    let translate_fn = match translate_fn {
+        Some(path) => quote!(#path),
+        None => quote!(tr),
+    };
+    let message = quote!(::context_attribute::__private::Message::from(#translate_fn(#key)));
+    match prefix.is_empty() {
+        true => message,
+        false => {
+            let fmt = format!("{}{{}}", escape(prefix));
+            quote!(::context_attribute::__private::Message::Owned(
+                format!(#fmt, #message)
+            ))
+        }
+    }
+}
+
+/// Build the expression that produces the context message from a `msg` option.
+///
+/// String literals are treated like a doc comment. Other expressions, such as `env!("...")` or
+/// `concat!(...)`, are formatted with their `Display` implementation.
+fn expression(
+    msg: &syn::Expr,
+    prefix: &str,
+    input: &syn::ItemFn,
+    options: &Options,
+) -> syn::Result<TokenStream> {
+    if let syn::Expr::Lit(syn::ExprLit {
+        lit: syn::Lit::Str(_),
+        ..
+    }) = msg
+    {
+        return interpolate(msg, prefix, input.sig.receiver().is_some(), options);
+    }
+    let fmt = format!("{}{{}}", escape(prefix));
+    Ok(quote_spanned!(msg.span()=>
+        ::context_attribute::__private::Message::Owned(format!(#fmt, #msg))
+    ))
+}
+
+/// Build a message by calling a method on `self`, as with `with = self.describe_error`.
+///
+/// The method takes `&self`, and returns anything that converts into a `Message`, such as a
+/// `String` or a `&'static str`.
+fn method_call(
+    method: &syn::ExprField,
+    prefix: &str,
+    input: &syn::ItemFn,
+) -> syn::Result<TokenStream> {
+    if input.sig.receiver().is_none() {
+        let msg = "`with` can only be used on methods";
+        return Err(syn::Error::new_spanned(method, msg));
+    }
+    let call = quote_spanned!(method.span()=> #method());
+    Ok(match prefix.is_empty() {
+        true => quote!(::context_attribute::__private::Message::from(#call)),
+        false => {
+            let fmt = format!("{}{{}}", escape(prefix));
+            quote!(::context_attribute::__private::Message::Owned(
+                format!(#fmt, #call)
+            ))
+        }
+    })
+}
+
+/// Build the expression that produces the context message from a doc comment.
+///
+/// Doc comments are interpolated: `{arg}` refers to a function argument, and `{self.field}` to a
+/// field on the receiver of a method. A format spec can follow a colon, like in `format!`, and
+/// braces can be escaped as `{{` and `}}`. Without placeholders the message is a `&'static str`.
+///
+/// The prefix is prepended to the message as-is. Literal doc comments are trimmed by `find_doc`
+/// already. Unless `no_trim` is set, the single leading space that `///` adds is stripped from
+/// other expressions, and any other whitespace is kept. With `literal`, placeholders aren't
+/// interpolated and braces don't need escaping.
+fn interpolate(
+    doc: &syn::Expr,
+    prefix: &str,
+    receiver: bool,
+    options: &Options,
+) -> syn::Result<TokenStream> {
+    let lit = match doc {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(lit),
+            ..
+        }) => lit,
+        doc => {
+            let doc = match !options.no_trim {
+                true => quote!({
+                    let doc: &'static str = #doc;
+                    doc.strip_prefix(' ').unwrap_or(doc)
+                }),
+                false => quote!(#doc),
+            };
+            return match prefix.is_empty() {
+                true => Ok(quote!(::context_attribute::__private::Message::Borrowed(#doc))),
+                false => {
+                    let fmt = format!("{}{{}}", escape(prefix));
+                    Ok(quote!(::context_attribute::__private::Message::Owned(
+                        format!(#fmt, #doc)
+                    )))
+                }
+            };
+        }
+    };
+
+    let text = lit.value();
+    if options.literal {
+        let plain = format!("{}{}", prefix, text);
+        return Ok(quote!(::context_attribute::__private::Message::Borrowed(#plain)));
+    }
+    let mut fmt = escape(prefix);
+    let mut plain = prefix.to_string();
+    let mut args = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                fmt.push_str("{{");
+                plain.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                fmt.push_str("}}");
+                plain.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            return Err(syn::Error::new(
+                                lit.span(),
+                                "unclosed `{` in doc comment, use `{{` to escape it",
+                            ))
+                        }
+                    }
+                }
+                let (path, spec) = match placeholder.find(':') {
+                    Some(i) => placeholder.split_at(i),
+                    None => (placeholder.as_str(), ""),
+                };
+                args.push(placeholder_expr(lit, path.trim(), receiver)?);
+                fmt.push('{');
+                fmt.push_str(spec);
+                fmt.push('}');
+            }
+            '}' => {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    "unmatched `}` in doc comment, use `}}` to escape it",
+                ))
+            }
+            c => {
+                fmt.push(c);
+                plain.push(c);
+            }
+        }
+    }
+
+    if args.is_empty() {
+        Ok(quote!(::context_attribute::__private::Message::Borrowed(#plain)))
+    } else {
+        Ok(quote!(::context_attribute::__private::Message::Owned(
+            format!(#fmt, #(#args),*)
+        )))
+    }
+}
+
+/// Escape braces in text so it can be used in a format string.
+fn escape(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+/// Parse the contents of a `{...}` placeholder into an expression.
+fn placeholder_expr(lit: &syn::LitStr, path: &str, receiver: bool) -> syn::Result<TokenStream> {
+    let invalid = || {
+        let msg = format!(
+            "invalid placeholder `{{{}}}` in doc comment, use `{{{{` to escape braces",
+            path
+        );
+        syn::Error::new(lit.span(), msg)
+    };
+
+    let mut segments = path.split('.').map(str::trim);
+    let head = match segments.next() {
+        Some("self") if !receiver => {
+            let msg = "`{self...}` can only be used in the doc comment of a method";
+            return Err(syn::Error::new(lit.span(), msg));
+        }
+        Some("self") => quote!(self),
+        Some(head) => {
+            let ident = syn::parse_str::<syn::Ident>(head).map_err(|_| invalid())?;
+            quote!(#ident)
+        }
+        None => return Err(invalid()),
+    };
+    let fields = segments
+        .map(|segment| syn::parse_str::<syn::Member>(segment).map_err(|_| invalid()))
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote!(#head #(.#fields)*))
+}
+
+/// Compute a short identifier for a function that stays the same between builds.
+///
+/// This is an FNV-1a hash of the crate name, the source file relative to the crate's manifest,
+/// the type of the annotated impl block or trait for methods, and the function name, folded to 16
+/// bits. The module path isn't visible to the macro, so the file stands in for it.
+fn error_id(input: &syn::ItemFn, options: &Options) -> syn::Result<String> {
+    if input.sig.receiver().is_some() && options.owner.is_none() {
+        let msg = "`id` on a method needs the type it's defined on, which the macro can only see \
+                   on an annotated impl block or trait, as in `#[context(id)] impl Config`";
+        return Err(syn::Error::new(input.sig.ident.span(), msg));
+    }
+    let krate = std::env::var("CARGO_CRATE_NAME").unwrap_or_default();
+    let span = proc_macro::Span::call_site();
+    // Paths to dependencies are absolute, so only the part in the crate is hashed.
+    let file = span
+        .local_file()
+        .and_then(|file| {
+            let dir = std::fs::canonicalize(std::env::var_os("CARGO_MANIFEST_DIR")?).ok()?;
+            let file = std::fs::canonicalize(file).ok()?;
+            let file = file.strip_prefix(dir).ok()?.components();
+            let file = file.map(|part| part.as_os_str().to_string_lossy().into_owned());
+            Some(file.collect::<Vec<_>>().join("/"))
+        })
+        .unwrap_or_else(|| span.file());
+    let owner = options.owner.as_deref().unwrap_or_default();
+    let name = input.sig.ident.to_string();
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in [krate.as_str(), file.as_str(), owner, &name]
+        .join("::")
+        .bytes()
+    {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    Ok(format!("{:04x}", (hash >> 16) ^ (hash & 0xffff)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::build_message;
+    use crate::options::Options;
+    use quote::quote;
+    use syn::parse::Parser;
+
+    fn build(options: proc_macro2::TokenStream, input: syn::ItemFn) -> syn::Result<String> {
+        let options = Options::parse.parse2(options)?;
+        Ok(build_message(&input, &options)?.text.to_string())
+    }
+
+    #[test]
+    fn message_from_doc_comment() {
+        let input = syn::parse_quote! {
+            /// Load the config
+            fn load() -> Result<(), Error> {}
+        };
+        let expected = quote!(::context_attribute::__private::Message::Borrowed(
+            "Load the config"
+        ));
+        assert_eq!(build(quote!(), input).unwrap(), expected.to_string());
+    }
+
+    #[test]
+    fn message_with_placeholders_and_prefix() {
+        let input = syn::parse_quote! {
+            /// Read {path:?}
+            fn read(path: &str) -> Result<(), Error> {}
+        };
+        let expected = quote!(::context_attribute::__private::Message::Owned(format!(
+            "db: Read {:?}",
+            path
+        )));
+        assert_eq!(
+            build(quote!(prefix = "db"), input).unwrap(),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn message_for_each_error_type() {
+        let input = syn::parse_quote! {
+            /// Load the config
+            fn load() -> Result<(), Error> {}
+        };
+        let options = Options::parse
+            .parse2(quote!(map(std::io::Error => "Read the config")))
+            .unwrap();
+        let message = build_message(&input, &options).unwrap();
+        assert_eq!(message.by_type.len(), 1);
+        assert!(message.notes.is_empty());
+    }
+
+    #[test]
+    fn truncate_literal_messages() {
+        let input = syn::parse_quote! {
+            /// Load the configuration file
+            fn load() -> Result<(), Error> {}
+        };
+        let expected = quote!(::context_attribute::__private::Message::Borrowed(
+            "Load the…"
+        ));
+        let message = build(quote!(max_len = 9), input).unwrap();
+        assert_eq!(message, expected.to_string());
+    }
+
+    #[test]
+    fn docs_in_cfg_attr() {
+        let input: syn::ItemFn = syn::parse_quote! {
+            #[cfg_attr(all(), doc = "Load")]
+            #[cfg_attr(not(any()), cfg_attr(all(not(any())), doc = "the config"))]
+            #[cfg_attr(any(), doc = "never")]
+            #[cfg_attr(unix, doc = "maybe")]
+            fn load() -> Result<(), Error> {}
+        };
+        let docs = super::docs(&input.attrs);
+        let expected: Vec<syn::Expr> =
+            vec![syn::parse_quote!("Load"), syn::parse_quote!("the config")];
+        assert_eq!(docs, expected);
+        assert!(super::conditional_docs(&input.attrs));
+    }
+
+    #[test]
+    fn missing_doc_comment() {
+        let input = syn::parse_quote! {
+            fn load() -> Result<(), Error> {}
+        };
+        let err = build(quote!(), input).unwrap_err();
+        assert_eq!(err.to_string(), "no doc comment provided");
+    }
+}
@@ -0,0 +1,562 @@
+//! The first stage: parsing the options passed to the attribute.
+
+use crate::{EYRE, JSON, PREFIXES_VAR, TOKIO};
+use quote::ToTokens;
+
+/// The options passed to the attribute, as in `#[context(...)]`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Options {
+    /// Prefix the message with a stable identifier for the function, like `[E#a1b2]`.
+    pub(crate) id: bool,
+    /// Look the message up by this key through a translation function, instead of using the doc
+    /// comment.
+    pub(crate) translate: Option<syn::LitStr>,
+    /// The translation function, `tr` by default.
+    pub(crate) translate_fn: Option<syn::Path>,
+    /// Look the message up by this code in the table of error codes, and prefix it with the code.
+    pub(crate) code: Option<syn::LitStr>,
+    /// A function to call with the message when the function succeeds.
+    pub(crate) on_ok: Option<syn::Path>,
+    /// A channel to send the message to when the function fails.
+    pub(crate) send_to: Option<syn::Path>,
+    /// The message to use if the function has no doc comment.
+    pub(crate) default: Option<syn::LitStr>,
+    /// Give errors from `?` in the body's `for` loops a context with the index of their item.
+    pub(crate) batch: bool,
+    /// Only attach the context in builds with `debug_assertions` enabled.
+    pub(crate) debug_only: bool,
+    /// An expression to use as the message instead of the doc comment, like `env!("...")`.
+    pub(crate) msg: Option<syn::Expr>,
+    /// An expression to use as the message instead in builds with `debug_assertions` enabled.
+    pub(crate) debug_msg: Option<syn::Expr>,
+    /// A method on `self` to call for the message instead of using the doc comment.
+    pub(crate) with: Option<syn::ExprField>,
+    /// Treat the body as the return code of a C function, and read the error from `errno` on `-1`.
+    pub(crate) errno: bool,
+    /// A predicate on the `Ok` value, bound as `result`, that turns it into an error, along with
+    /// the string it was parsed from.
+    pub(crate) err_if: Option<(syn::LitStr, syn::Expr)>,
+    /// Print the error to stderr where it occurs.
+    pub(crate) eprintln: bool,
+    /// The level to print the error at with `eprintln`, `error` by default, or with `or_else`,
+    /// `warn` by default.
+    pub(crate) level: Option<syn::LitStr>,
+    /// The number of characters to truncate the message to.
+    pub(crate) max_len: Option<usize>,
+    /// Serialize the message, arguments, and location as JSON.
+    pub(crate) json: bool,
+    /// Messages to use instead of the doc comment when the error was caused by a given type.
+    pub(crate) map: Vec<(syn::Type, syn::LitStr)>,
+    /// Append the raw OS error code of an `io::Error` the error was caused by.
+    pub(crate) os_error: bool,
+    /// A value to return as `Ok` instead of the error, which is printed to stderr.
+    pub(crate) or_else: Option<syn::Expr>,
+    /// A category to prefix the message with, like `storage: `.
+    pub(crate) prefix: Option<syn::LitStr>,
+    /// Follow the message with this separator and the error it's attached to.
+    pub(crate) sep: Option<syn::LitStr>,
+    /// Leave the item as-is, to exclude a method from an annotated trait or impl block.
+    pub(crate) skip: bool,
+    /// Only use the first sentence of the doc comment.
+    pub(crate) summary: bool,
+    /// Append the name of the current thread to the message.
+    pub(crate) thread: bool,
+    /// A function returning the correlation id of the current request, to append to the message.
+    pub(crate) correlation_id: Option<syn::Path>,
+    /// Use the doc comment as the message. This is the default, and can't be combined with `msg`,
+    /// `t`, `with`, or `catalog`.
+    pub(crate) doc: bool,
+    /// Append the function's arguments to the message.
+    pub(crate) args: bool,
+    /// Append the location of the function to the message.
+    pub(crate) location: bool,
+    /// Only attach the context if `CONTEXT_VERBOSE` is set at runtime.
+    pub(crate) verbose: bool,
+    /// A variable holding the current attempt of a retry loop, to append to the message.
+    pub(crate) attempt: Option<syn::Expr>,
+    /// An enum variant to wrap the error in, along with the message, instead of attaching a
+    /// context.
+    pub(crate) variant: Option<syn::Path>,
+    /// The fields to set on a struct-like `variant`, besides its `source`.
+    pub(crate) variant_fields: Option<syn::punctuated::Punctuated<syn::FieldValue, syn::Token![,]>>,
+    /// Use the doc comment verbatim, without interpolating placeholders.
+    pub(crate) literal: bool,
+    /// The separator to join the lines of a multi-line doc comment with, a space by default.
+    pub(crate) join: Option<syn::LitStr>,
+    /// A note to add to the error report, for the `eyre` backend.
+    pub(crate) note: Option<syn::LitStr>,
+    /// Use the first paragraph of the doc comment as the message, and add the others as notes.
+    pub(crate) notes: bool,
+    /// Use the doc comment as-is, instead of stripping the leading space `///` adds.
+    pub(crate) no_trim: bool,
+    /// Leave functions without a doc comment unchanged, instead of reporting an error.
+    pub(crate) optional_doc: bool,
+    /// The name to bind the closure, or future, that holds the body to. It's unnamed by default.
+    ///
+    /// This is undocumented, and only meant for reading the expanded code, as with `cargo expand`,
+    /// or for debugging interactions with other macros.
+    pub(crate) inner_name: Option<syn::Ident>,
+    /// The type of the impl block or the trait a method is expanded as part of, so it's part of
+    /// the method's `id`. It isn't parsed, but set when expanding an annotated block.
+    pub(crate) owner: Option<String>,
+}
+
+impl Options {
+    pub(crate) fn parse(input: syn::parse::ParseStream<'_>) -> syn::Result<Self> {
+        let mut options = Options::default();
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("args") {
+                options.args = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("attempt") {
+                options.attempt = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("batch") {
+                options.batch = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("doc") {
+                options.doc = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("debug_only") {
+                options.debug_only = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("default") {
+                options.default = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("err_if") {
+                let predicate: syn::LitStr = meta.value()?.parse()?;
+                let expr = predicate.parse::<syn::Expr>().map_err(|_| {
+                    let msg = "`err_if` must be an expression on the `Ok` value, as in \
+                               `err_if = \"result == -1\"`";
+                    syn::Error::new(predicate.span(), msg)
+                })?;
+                options.err_if = Some((predicate, expr));
+                return Ok(());
+            }
+            if meta.path.is_ident("errno") {
+                options.errno = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("eprintln") {
+                options.eprintln = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("id") {
+                options.id = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("t") {
+                let key: syn::LitStr = meta.value()?.parse()?;
+                let valid = |c: char| c.is_alphanumeric() || "._-:".contains(c);
+                if key.value().is_empty() || !key.value().chars().all(valid) {
+                    let msg = "translation keys must be non-empty and only contain letters, \
+                               digits, `.`, `_`, `-`, or `:`";
+                    return Err(syn::Error::new(key.span(), msg));
+                }
+                options.translate = Some(key);
+                return Ok(());
+            }
+            if meta.path.is_ident("inner_name") {
+                let name: syn::LitStr = meta.value()?.parse()?;
+                let ident = syn::parse_str::<syn::Ident>(&name.value()).map_err(|_| {
+                    syn::Error::new(name.span(), "`inner_name` must be a valid identifier")
+                })?;
+                options.inner_name = Some(syn::Ident::new(&ident.to_string(), name.span()));
+                return Ok(());
+            }
+            if meta.path.is_ident("join") {
+                options.join = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("max_len") {
+                let max_len: syn::LitInt = meta.value()?.parse()?;
+                match max_len.base10_parse::<usize>() {
+                    Ok(max) if max > 0 => options.max_len = Some(max),
+                    _ => {
+                        let msg = "`max_len` must be a positive integer, as in `max_len = 120`";
+                        return Err(syn::Error::new(max_len.span(), msg));
+                    }
+                }
+                return Ok(());
+            }
+            if meta.path.is_ident("level") {
+                let level: syn::LitStr = meta.value()?.parse()?;
+                let levels = ["trace", "debug", "info", "warn", "error"];
+                if !levels.contains(&level.value().as_str()) {
+                    let msg = "expected one of `trace`, `debug`, `info`, `warn`, or `error`";
+                    return Err(syn::Error::new(level.span(), msg));
+                }
+                options.level = Some(level);
+                return Ok(());
+            }
+            if meta.path.is_ident("location") {
+                options.location = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("literal") {
+                options.literal = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("json") {
+                if !JSON {
+                    return Err(
+                        meta.error("`json` requires the `json` feature of context-attribute")
+                    );
+                }
+                options.json = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("map") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                while !content.is_empty() {
+                    let ty: syn::Type = content.parse()?;
+                    content.parse::<syn::Token![=>]>()?;
+                    options.map.push((ty, content.parse()?));
+                    if !content.is_empty() {
+                        content.parse::<syn::Token![,]>()?;
+                    }
+                }
+                return Ok(());
+            }
+            if meta.path.is_ident("note") {
+                if !EYRE {
+                    return Err(
+                        meta.error("`note` requires the `eyre` feature of context-attribute")
+                    );
+                }
+                options.note = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("debug_msg") {
+                options.debug_msg = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("msg") {
+                options.msg = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("with") {
+                let expr: syn::Expr = meta.value()?.parse()?;
+                let method = match &expr {
+                    syn::Expr::Field(method) => match (&*method.base, &method.member) {
+                        (syn::Expr::Path(base), syn::Member::Named(_))
+                            if base.path.is_ident("self") =>
+                        {
+                            Some(method.clone())
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let method = method.ok_or_else(|| {
+                    let msg = "expected a method on `self`, as in `with = self.describe_error`";
+                    syn::Error::new_spanned(&expr, msg)
+                })?;
+                options.with = Some(method);
+                return Ok(());
+            }
+            if meta.path.is_ident("notes") {
+                if !EYRE {
+                    return Err(
+                        meta.error("`notes` requires the `eyre` feature of context-attribute")
+                    );
+                }
+                options.notes = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("no_trim") {
+                options.no_trim = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("on_ok") {
+                options.on_ok = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("send_to") {
+                options.send_to = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("optional_doc") {
+                options.optional_doc = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("prefix") {
+                let prefix: syn::LitStr = meta.value()?.parse()?;
+                check_prefix(&prefix)?;
+                options.prefix = Some(prefix);
+                return Ok(());
+            }
+            if meta.path.is_ident("sep") {
+                options.sep = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("skip") {
+                options.skip = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("summary") {
+                options.summary = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("catalog") {
+                let code: syn::LitStr = meta.value()?.parse()?;
+                let valid = |c: char| c.is_alphanumeric() || "._-".contains(c);
+                if code.value().is_empty() || !code.value().chars().all(valid) {
+                    let msg = "error codes must be non-empty and only contain letters, digits, \
+                               `.`, `_`, or `-`";
+                    return Err(syn::Error::new(code.span(), msg));
+                }
+                options.code = Some(code);
+                return Ok(());
+            }
+            if meta.path.is_ident("correlation_id") {
+                if meta.input.peek(syn::Token![=]) {
+                    options.correlation_id = Some(meta.value()?.parse()?);
+                    return Ok(());
+                }
+                if !TOKIO {
+                    let msg =
+                        "`correlation_id` requires the `tokio` feature of context-attribute, \
+                               or a function to get the id with, as in \
+                               `correlation_id = request_id`";
+                    return Err(meta.error(msg));
+                }
+                options.correlation_id = Some(syn::parse_quote!(
+                    ::context_attribute::__private::correlation_id
+                ));
+                return Ok(());
+            }
+            if meta.path.is_ident("thread") {
+                options.thread = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("variant") {
+                match meta.value()?.parse()? {
+                    syn::Expr::Path(variant) if variant.qself.is_none() => {
+                        options.variant = Some(variant.path);
+                    }
+                    syn::Expr::Struct(variant)
+                        if variant.qself.is_none() && variant.rest.is_none() =>
+                    {
+                        options.variant = Some(variant.path);
+                        options.variant_fields = Some(variant.fields);
+                    }
+                    expr => {
+                        let msg = "expected an enum variant, as in `variant = Error::Io` or \
+                                   `variant = Error::Io { path }`";
+                        return Err(syn::Error::new_spanned(expr, msg));
+                    }
+                }
+                return Ok(());
+            }
+            if meta.path.is_ident("or_else") {
+                options.or_else = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("os_error") {
+                options.os_error = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("verbose") {
+                options.verbose = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("t_fn") {
+                options.translate_fn = Some(meta.value()?.parse()?);
+                return Ok(());
+            }
+            let path = meta.path.to_token_stream().to_string().replace(' ', "");
+            Err(meta.error(format!("unknown #[context] option `{}`", path)))
+        });
+        syn::parse::Parser::parse2(parser, input.parse()?)?;
+        if let (Some(path), None) = (&options.translate_fn, &options.translate) {
+            return Err(syn::Error::new_spanned(
+                path,
+                "`t_fn` requires a translation key, as in `t = \"...\"`",
+            ));
+        }
+        if let (true, Some(msg)) = (options.doc, &options.msg) {
+            return Err(syn::Error::new_spanned(
+                msg,
+                "`msg` can't be combined with `doc`",
+            ));
+        }
+        if let (true, Some(key)) = (options.doc, &options.translate) {
+            return Err(syn::Error::new(
+                key.span(),
+                "a translation key can't be combined with `doc`",
+            ));
+        }
+        if let (Some(msg), Some(_)) = (&options.msg, &options.translate) {
+            return Err(syn::Error::new_spanned(
+                msg,
+                "`msg` can't be combined with a translation key",
+            ));
+        }
+        if let Some(method) = &options.with {
+            let other = match (&options.msg, &options.translate, options.doc) {
+                (Some(_), _, _) => Some("`msg`"),
+                (None, Some(_), _) => Some("a translation key"),
+                (None, None, true) => Some("`doc`"),
+                (None, None, false) => None,
+            };
+            if let Some(other) = other {
+                let msg = format!("`with` can't be combined with {}", other);
+                return Err(syn::Error::new_spanned(method, msg));
+            }
+        }
+        if let Some(code) = &options.code {
+            let other = match (&options.msg, &options.translate, &options.with, options.doc) {
+                (Some(_), _, _, _) => Some("`msg`"),
+                (None, Some(_), _, _) => Some("a translation key"),
+                (None, None, Some(_), _) => Some("`with`"),
+                (None, None, None, true) => Some("`doc`"),
+                (None, None, None, false) => None,
+            };
+            if let Some(other) = other {
+                let msg = format!("`catalog` can't be combined with {}", other);
+                return Err(syn::Error::new(code.span(), msg));
+            }
+        }
+        // `default` is only a fallback for the doc comment, which these replace.
+        if let Some(default) = &options.default {
+            let other = match (
+                &options.code,
+                &options.msg,
+                &options.translate,
+                &options.with,
+            ) {
+                (Some(_), _, _, _) => Some("`catalog`"),
+                (None, Some(_), _, _) => Some("`msg`"),
+                (None, None, Some(_), _) => Some("a translation key"),
+                (None, None, None, Some(_)) => Some("`with`"),
+                (None, None, None, None) => None,
+            };
+            if let Some(other) = other {
+                let msg = format!("`default` can't be combined with {}", other);
+                return Err(syn::Error::new(default.span(), msg));
+            }
+        }
+        if let (Some(level), false) = (
+            &options.level,
+            options.eprintln || options.or_else.is_some(),
+        ) {
+            let msg = "`level` requires `eprintln` or `or_else`";
+            return Err(syn::Error::new(level.span(), msg));
+        }
+        // Without a context, the error wouldn't fit in the variant, and `on_ok` couldn't tell
+        // whether an error occurred.
+        if let (true, Some(path)) = (options.verbose, &options.variant) {
+            let msg = "`verbose` can't be combined with `variant`";
+            return Err(syn::Error::new_spanned(path, msg));
+        }
+        if let (true, Some(path)) = (options.verbose, &options.on_ok) {
+            let msg = "`verbose` can't be combined with `on_ok`";
+            return Err(syn::Error::new_spanned(path, msg));
+        }
+        if let (Some((predicate, _)), Some(_)) = (&options.err_if, &options.variant) {
+            let msg = "`err_if` can't be combined with `variant`";
+            return Err(syn::Error::new(predicate.span(), msg));
+        }
+        if let (true, Some(path)) = (options.batch, &options.variant) {
+            let msg = "`batch` can't be combined with `variant`";
+            return Err(syn::Error::new_spanned(path, msg));
+        }
+        if options.errno && options.debug_only {
+            let msg = "`errno` can't be combined with `debug_only`";
+            return Err(syn::Error::new(proc_macro2::Span::call_site(), msg));
+        }
+        if let (true, Some(path)) = (options.errno, &options.variant) {
+            let msg = "`errno` can't be combined with `variant`";
+            return Err(syn::Error::new_spanned(path, msg));
+        }
+        if let (true, Some(path)) = (options.os_error, &options.variant) {
+            let msg = "`os_error` can't be combined with `variant`";
+            return Err(syn::Error::new_spanned(path, msg));
+        }
+        if let (Some(path), Some((ty, _))) = (&options.variant, options.map.first()) {
+            let mut err = syn::Error::new_spanned(path, "`variant` can't be combined with `map`");
+            err.combine(syn::Error::new_spanned(ty, "`map` is used here"));
+            return Err(err);
+        }
+        Ok(options)
+    }
+}
+
+/// Check a `prefix` against the allowlist in the `CONTEXT_ATTRIBUTE_PREFIXES` environment
+/// variable, if it's set.
+fn check_prefix(prefix: &syn::LitStr) -> syn::Result<()> {
+    let allowed = match std::env::var(PREFIXES_VAR) {
+        Ok(allowed) => allowed,
+        Err(_) => return Ok(()),
+    };
+    let allowed: Vec<_> = allowed
+        .split(',')
+        .map(str::trim)
+        .filter(|prefix| !prefix.is_empty())
+        .collect();
+    if allowed.contains(&prefix.value().as_str()) {
+        return Ok(());
+    }
+    let allowed = allowed
+        .iter()
+        .map(|prefix| format!("`{}`", prefix))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let msg = format!(
+        "prefix `{}` isn't allowed by {}, expected one of: {}",
+        prefix.value(),
+        PREFIXES_VAR,
+        allowed
+    );
+    Err(syn::Error::new(prefix.span(), msg))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Options;
+    use quote::quote;
+    use syn::parse::Parser;
+
+    fn parse(tokens: proc_macro2::TokenStream) -> syn::Result<Options> {
+        Options::parse.parse2(tokens)
+    }
+
+    #[test]
+    fn parse_flags_and_values() {
+        let options = parse(quote!(summary, sep = ": ", inner_name = "body")).unwrap();
+        assert!(options.summary);
+        assert!(!options.verbose);
+        assert_eq!(options.sep.unwrap().value(), ": ");
+        assert_eq!(options.inner_name.unwrap(), "body");
+        assert!(options.translate.is_none());
+    }
+
+    #[test]
+    fn reject_unknown_options() {
+        let err = parse(quote!(summary, frobnicate)).unwrap_err();
+        assert_eq!(err.to_string(), "unknown #[context] option `frobnicate`");
+    }
+
+    #[test]
+    fn reject_conflicting_options() {
+        let err = parse(quote!(msg = "Load", t = "config.load")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`msg` can't be combined with a translation key"
+        );
+        let err = parse(quote!(catalog = "E1001", doc)).unwrap_err();
+        assert_eq!(err.to_string(), "`catalog` can't be combined with `doc`");
+        let err = parse(quote!(msg = env!("NAME"), default = "Load")).unwrap_err();
+        assert_eq!(err.to_string(), "`default` can't be combined with `msg`");
+        let err = parse(quote!(level = "warn")).unwrap_err();
+        assert_eq!(err.to_string(), "`level` requires `eprintln` or `or_else`");
+    }
+}
@@ -0,0 +1,143 @@
+//! Recognizing the shapes of return types.
+
+/// Check whether a type is a path whose last segment is `name`.
+pub(crate) fn last_segment_is(ty: &syn::Type, name: &str) -> bool {
+    match ty {
+        syn::Type::Path(ty) => ty.path.segments.last().is_some_and(|seg| seg.ident == name),
+        _ => false,
+    }
+}
+
+/// Get the `T` in a `Result<T, E>`.
+pub(crate) fn result_ok_type(ty: &syn::Type) -> Option<&syn::Type> {
+    let seg = match ty {
+        syn::Type::Path(ty) => ty
+            .path
+            .segments
+            .last()
+            .filter(|seg| seg.ident == "Result")?,
+        _ => return None,
+    };
+    match &seg.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 2 => match &args.args[0] {
+            syn::GenericArgument::Type(ok) => Some(ok),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get the `E` in a `Result<T, E>`.
+pub(crate) fn result_err_type(ty: &syn::Type) -> Option<&syn::Type> {
+    let seg = match ty {
+        syn::Type::Path(ty) => ty
+            .path
+            .segments
+            .last()
+            .filter(|seg| seg.ident == "Result")?,
+        _ => return None,
+    };
+    match &seg.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 2 => match &args.args[1] {
+            syn::GenericArgument::Type(err) => Some(err),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get the type parameter a type is, like `E` in `fn run<E>() -> E`, if it's one.
+///
+/// Paths into a parameter, like `S::Error`, aren't the parameter itself and aren't matched.
+pub(crate) fn generic_param<'a>(
+    ty: &syn::Type,
+    generics: &'a syn::Generics,
+) -> Option<&'a syn::Ident> {
+    let ident = match ty {
+        syn::Type::Path(ty) if ty.qself.is_none() => ty.path.get_ident()?,
+        _ => return None,
+    };
+    generics
+        .type_params()
+        .map(|param| &param.ident)
+        .find(|param| *param == ident)
+}
+
+/// Get the `Output` type of a `Pin<Box<dyn Future<Output = T>>>`.
+pub(crate) fn boxed_future_output(ty: &syn::Type) -> Option<&syn::Type> {
+    let pinned = last_generic_arg(ty, "Pin")?;
+    match last_generic_arg(pinned, "Box")? {
+        syn::Type::TraitObject(boxed) => future_output(&boxed.bounds),
+        _ => None,
+    }
+}
+
+/// Get the `Output` type of an `impl Future<Output = T>`.
+pub(crate) fn impl_future_output(ty: &syn::Type) -> Option<&syn::Type> {
+    match ty {
+        syn::Type::ImplTrait(ty) => future_output(&ty.bounds),
+        _ => None,
+    }
+}
+
+/// Get the `Output` type from a list of bounds that includes `Future<Output = T>`.
+fn future_output(
+    bounds: &syn::punctuated::Punctuated<syn::TypeParamBound, syn::Token![+]>,
+) -> Option<&syn::Type> {
+    bounds.iter().find_map(|bound| match bound {
+        syn::TypeParamBound::Trait(bound) => {
+            let future = bound
+                .path
+                .segments
+                .last()
+                .filter(|seg| seg.ident == "Future")?;
+            match &future.arguments {
+                syn::PathArguments::AngleBracketed(args) => {
+                    args.args.iter().find_map(|arg| match arg {
+                        syn::GenericArgument::AssocType(assoc) if assoc.ident == "Output" => {
+                            Some(&assoc.ty)
+                        }
+                        _ => None,
+                    })
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+/// Get the single type argument of a path type whose last segment is `name`, e.g. `T` in `Box<T>`.
+fn last_generic_arg<'a>(ty: &'a syn::Type, name: &str) -> Option<&'a syn::Type> {
+    let seg = match ty {
+        syn::Type::Path(ty) => ty.path.segments.last().filter(|seg| seg.ident == name)?,
+        _ => return None,
+    };
+    match &seg.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            syn::GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+/// Replace each `impl Trait` in a type with `_`, as in `Result<_, Error>` for
+/// `Result<impl Display, Error>`.
+///
+/// `impl Trait` is only allowed in the return types of functions, so the types the body's result
+/// is bound to leave its concrete type to be inferred instead.
+pub(crate) fn infer_impl_traits(ty: &syn::Type) -> syn::Type {
+    struct Infer;
+    impl syn::visit_mut::VisitMut for Infer {
+        fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+            match ty {
+                syn::Type::ImplTrait(_) => *ty = syn::parse_quote!(_),
+                ty => syn::visit_mut::visit_type_mut(self, ty),
+            }
+        }
+    }
+    let mut ty = ty.clone();
+    syn::visit_mut::VisitMut::visit_type_mut(&mut Infer, &mut ty);
+    ty
+}
@@ -8,9 +8,9 @@
 //!
 //! ## Examples
 //!
-//! ```rust
+//! ```rust,no_run
 //! use context_attribute::context;
-//! use failure::{ensure, ResultExt};
+//! use failure::ensure;
 //!
 //! /// Square a number if it's less than 10.
 //! #[context]
@@ -20,9 +20,9 @@
 //! }
 //!
 //! fn main() -> Result<(), failure::Error> {
-//!     let args = std::env::args();
+//!     let mut args = std::env::args();
 //!     ensure!(args.len() == 2, "usage: square <num>");
-//!     let input = args.skip(1).next().unwrap().parse()?;
+//!     let input = args.nth(1).unwrap().parse()?;
 //!
 //!     println!("result is {}", square(input)?);
 //!
@@ -33,87 +33,647 @@
 //! ```sh
 //! $ cargo run --example square 12
 //! Error: ErrorMessage { msg: "Number was too large" }
+//!
 //! Square a number if it's less than 10.
 //! ```
+//!
+//! ## Backends
+//!
+//! The context is attached by the error library the function returns. Each one is behind a
+//! feature, and several can be enabled at once:
+//!
+//! - __`failure`__ _(default)_: functions returning `Result<T, failure::Error>`.
+//! - __`anyhow`__: functions returning `anyhow::Result<T>`.
+//! - __`error-stack`__: functions returning `Result<T, error_stack::Report<C>>`. The message is
+//!   attached to the report like `.attach("...")` would, which keeps its context type `C`, so
+//!   `.change_context(...)` is still up to the function. See `examples/error_stack.rs`.
+//! - __`eyre`__: functions returning `eyre::Result<T>`. Enables the `note` option, which adds a
+//!   [`color-eyre`] note section to the report. See `examples/eyre.rs`.
+//! - __`miette`__: functions returning `miette::Result<T>`, for CLI tools using [`miette`]'s
+//!   diagnostics. The context wraps the error like `.wrap_err("...")` would, so the diagnostic,
+//!   with its source snippet and help, is still rendered below it. See `examples/miette.rs`.
+//!   Errors converted with `.into_diagnostic()` are wrapped by `miette`, so `map` can only match
+//!   errors that implement `Diagnostic` themselves.
+//! - __`syn`__: functions returning `Result<T, syn::Error>`, like the helpers of a proc-macro
+//!   crate. A `syn::Error` has no causes, so the message is prepended to each of its errors, as in
+//!   `Expand the struct: expected identifier`, and their spans are kept. See `tests/syn.rs`.
+//!
+//! [`color-eyre`]: https://docs.rs/color-eyre
+//! [`miette`]: https://docs.rs/miette
+//!
+//! Only the enabled backends are depended on, so an `anyhow`-only crate can use
+//! `default-features = false, features = ["anyhow"]` and won't pull in `failure`.
+//!
+//! With `anyhow`, a plain doc comment is attached as a `&'static str` like `.context("...")` would,
+//! and an interpolated one as a `String` formatted on the error path like
+//! `.with_context(|| format!(...))` would, so the generated code costs the same as the cheapest
+//! hand-written call for each case. `benches/context.rs` compares the two.
+//!
+//! Which return types support a context is decided by trait resolution rather than by name, so
+//! type aliases such as `type Fallible<T> = Result<T, failure::Error>` work too, as do
+//! type-alias `impl Trait`s such as `type Label = Result<impl Display, failure::Error>` on
+//! nightly. Functions returning an `Option` are accepted as well, but since `None` has nowhere to
+//! put a message, it's passed through as-is. Functions returning a `std::ops::ControlFlow`, as
+//! state machines often do, get the context on the error they break with, as in
+//! `ControlFlow<failure::Error, State>`.
+//!
+//! Other result-like types can support a context by implementing [`WithContext`].
+//!
+//! Other error types, such as `&'static str` in `Result<T, &'static str>`, have nowhere to put
+//! the context, and are a compile error pointing at the return type.
+//!
+//! Generic and associated error types need to be constrained to a backend's error type, as in
+//! `fn run<S: Service<Error = failure::Error>>(s: S) -> Result<S::Output, S::Error>`, since the
+//! context can't be attached to an arbitrary error. A function's own type parameters can't be
+//! constrained that way, so returning one, as in `fn run<E>(...) -> Result<T, E>`, is a compile
+//! error. Functions that take generic errors should return a backend's error type instead, and
+//! convert to it, as in `fn run<E: Into<anyhow::Error>>(...) -> anyhow::Result<T>` with
+//! `.map_err(Into::into)`, or `fn run<E: failure::Fail>(...) -> Result<T, failure::Error>` with
+//! `?`.
+//!
+//! Only the function's body is rewritten. Its signature and its other attributes, like
+//! `#[must_use]` or `#[inline]`, are kept as they are, so callers still get their lints.
+//!
+//! ## Traits and impl blocks
+//!
+//! `#[context]` can also go on a trait definition, where it applies to each default method that
+//! returns a value, using the method's doc comment. Options on the trait apply to every one of
+//! them. Required methods have no body to wrap and are skipped, as are default methods with a
+//! `#[context]` of their own.
+//!
+//! On an impl block, it likewise applies to each method that returns a value. As for functions,
+//! the return type is left to the compiler, so aliases like `Fallible<T>`, methods returning
+//! `impl Future`, and those `#[async_trait]` rewrote into boxed futures all get the context, and
+//! methods returning a type that can't carry one are an error. Mark a method with
+//! `#[context(skip)]` to leave it as-is, such as a getter, one whose errors already read well, or
+//! one another attribute generates its body for. The marker does nothing on its own, so macros can
+//! put it on the methods they generate whether or not they end up in an annotated block.
+//!
+//! Only the methods written in the block are seen. Those that macro invocations in the block
+//! generate aren't expanded yet, and derives generate impls of their own, so neither get the
+//! context. To exclude a whole impl, don't annotate it, and annotate its methods instead.
+//!
+//! Doc comments attached with `#[cfg_attr(..., doc = "...")]` work on functions, since the
+//! compiler expands them first. It doesn't do that for the methods of an annotated trait, so there
+//! the macro only sees the doc if its cfg is trivially true, like `all()`. Put `#[context]` on such
+//! methods themselves to use their doc.
+//!
+//! Only the bodies of methods are rewritten, into a closure or an `async` block, so their
+//! signatures stay the same and annotated traits can still be used as `dyn Trait`.
+//!
+//! ## Context stack
+//!
+//! With the `context-stack` feature enabled, annotated functions push their context onto a stack
+//! while they run, and [`context_stack`] returns the contexts currently entered. This can be used
+//! to log the logical call path at the point an error occurs, even across `.await` points: an
+//! annotated `async fn` pushes its context every time it's polled, rather than once when it's
+//! called.
+//!
+//! ## Correlation ids
+//!
+//! In async services, `#[context(correlation_id)]` appends the id of the request being handled to
+//! the message, as in `Load the user (correlation id req-42)`, to tie errors to the requests they
+//! happened in. With the `tokio` feature enabled, the id is read from the [`CORRELATION_ID`]
+//! task-local, which the service sets around each request. Other sources can be used by passing a
+//! function returning an `Option` of the id, as in `#[context(correlation_id = my::request_id)]`,
+//! which works without the feature. Nothing is appended if there's no id.
+//!
+//! ```rust
+//! # #[cfg(feature = "tokio")]
+//! # {
+//! use context_attribute::{context, CORRELATION_ID};
+//!
+//! /// Load user {id}
+//! #[context(correlation_id)]
+//! async fn load_user(id: u32) -> Result<String, failure::Error> {
+//!     failure::bail!("no such user")
+//! }
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+//! let request = CORRELATION_ID.scope("req-42".to_string(), load_user(12));
+//! let err = runtime.block_on(request).unwrap_err();
+//! assert_eq!(err.to_string(), "Load user 12 (correlation id req-42)");
+//! # }
+//! ```
+//!
+//! ## Interpolation
+//!
+//! Doc comments can refer to the function's arguments as `{arg}`, and methods can refer to fields
+//! on their receiver as `{self.field}`. Placeholders take a format spec like `format!` does, e.g.
+//! `{path:?}`, and literal braces are written as `{{` and `}}`. The message is only formatted when
+//! an error occurs, so the success path doesn't pay for it.
+//!
+//! ```rust
+//! use context_attribute::context;
+//! use failure::{bail, Error};
+//!
+//! struct Job {
+//!     id: usize,
+//! }
+//!
+//! impl Job {
+//!     /// Failed processing job {self.id} with {input:?}
+//!     #[context]
+//!     fn process(&self, input: &str) -> Result<(), Error> {
+//!         bail!("invalid input");
+//!     }
+//! }
+//!
+//! let err = Job { id: 12 }.process("hi").unwrap_err();
+//! assert_eq!(err.to_string(), r#"Failed processing job 12 with "hi""#);
+//! ```
+//!
+//! Interpolated arguments are borrowed after the function body has run, so they can't be moved
+//! out of inside the body.
+//!
+//! ## async-trait
+//!
+//! `#[context]` works on methods in an [`#[async_trait]`][async-trait] impl block. Put
+//! `#[async_trait]` on the impl block and `#[context]` on the methods: the impl block's attribute
+//! expands first and turns each method into one returning a boxed future, whose output
+//! `#[context]` then wraps. Because the boxed future takes ownership of the arguments, only
+//! `{self...}` placeholders can be interpolated in this position. See
+//! `examples/async_trait.rs`.
+//!
+//! [async-trait]: https://docs.rs/async-trait
+//!
+//! ## impl Future
+//!
+//! Functions that return an `impl Future<Output = Result<T, E>>` instead of being an `async fn`,
+//! usually by returning an `async move` block, are supported as well. The future the body returns
+//! is awaited in a new one, whose output gets the context. Like with `#[async_trait]`, the new
+//! future takes ownership of the arguments, so interpolated arguments need to be `Copy` or not
+//! moved into the body's future.
+//!
+//! ## tokio::main
+//!
+//! `#[context]` can go on a `#[tokio::main] async fn main()`, or on any other function with a
+//! runtime attribute like `#[tokio::test]`. Put `#[context]` above the runtime attribute, so it
+//! expands first and wraps the `async fn` as written, before the runtime attribute turns it into a
+//! function blocking on it. The other order works too, but then `#[context]` sees a sync function
+//! and only wraps the call that blocks on the body. See `examples/tokio_main.rs`.
+//!
+//! ```rust,no_run
+//! use context_attribute::context;
+//!
+//! /// Run the server
+//! #[context]
+//! #[tokio::main]
+//! async fn main() -> Result<(), failure::Error> {
+//!     let config = tokio::task::spawn_blocking(|| std::fs::read_to_string("config.toml")).await??;
+//!     println!("{}", config);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## Strict mode
+//!
+//! By default the macro is lenient and degrades gracefully when it can't make sense of its input.
+//! Enabling the `strict` feature turns the following into compile errors instead, so teams can
+//! enforce good context hygiene in CI:
+//!
+//! - __Empty doc comments:__ by default the function name is used as the context.
+//! - __Functions without a return type:__ by default these are emitted unchanged.
+//!
+//! Strict mode also ties documentation to fallibility. A function without a doc comment, or a
+//! `msg`, `t`, `with`, `catalog`, or `default`, is emitted unchanged if its body can't return an
+//! error, and is an error otherwise, even with `optional_doc`, so every function that can fail
+//! describes what it does. Bodies that use `?`, `Err`, or macros like `bail!` are considered
+//! fallible. With `lint-infallible` enabled too, annotating an infallible function is always an
+//! error.
+//!
+//! ## Infallible bodies
+//!
+//! Enabling the `lint-infallible` feature warns about annotated functions whose body can't return
+//! an error, such as one that always returns `Ok(...)` without using `?`, since the context is
+//! never attached there. With `strict` enabled too, this is a compile error. The check only looks
+//! at the body's syntax and gives up on anything it isn't sure about, like calls to macros other
+//! than `println!`, `format!`, and the like, so it's off by default.
+//!
+//! ## Error catalog
+//!
+//! Enabling the `catalog` feature and setting the `CONTEXT_ATTRIBUTE_CATALOG` environment variable
+//! to a path when building makes the macro append each annotated function's context to that file,
+//! to aggregate a catalog of the user-facing errors for documentation or review. Each context is
+//! recorded as written, before interpolation, on a line of the form `file:line<TAB>function<TAB>
+//! context`, with tabs, newlines, and backslashes escaped. The file is locked while writing, so
+//! parallel builds don't interleave their lines.
+//!
+//! The macro only runs for code that's being recompiled, so start from an empty file and a clean
+//! build, as in `cargo clean && CONTEXT_ATTRIBUTE_CATALOG=errors.tsv cargo build --features
+//! context-attribute/catalog`.
+//!
+//! ## Error codes
+//!
+//! Codebases with a formal catalog of error codes can keep the messages in a table, and refer to
+//! them by code with `#[context(catalog = "E1001")]` instead of a doc comment. The table is a file
+//! with a code and its message on each line, separated by whitespace, and lines starting with `#`
+//! are comments:
+//!
+//! ```text
+//! E1001  Read the config from {path}
+//! E1002  Parse the port from {input:?}
+//! ```
+//!
+//! Point the `CONTEXT_ATTRIBUTE_CODES` environment variable at the table when building, usually
+//! from `.cargo/config.toml`. Relative paths are resolved against the crate's directory:
+//!
+//! ```toml
+//! [env]
+//! CONTEXT_ATTRIBUTE_CODES = { value = "errors/codes.tsv", relative = true }
+//! ```
+//!
+//! The message is looked up when compiling, so an unknown code is a compile error, and it's
+//! interpolated like a doc comment. The context is the code followed by the message, as in
+//! `[E1001] Read the config from config.toml`, and it goes before the `prefix` if there's one.
+//! Functions are recompiled when the table changes. See `examples/error_codes.rs`.
 
 #![forbid(unsafe_code, future_incompatible, rust_2018_idioms)]
 #![deny(missing_debug_implementations, nonstandard_style)]
-#![warn(missing_docs, missing_doc_code_examples)]
+#![warn(missing_docs)]
 #![cfg_attr(test, deny(warnings))]
-#![recursion_limit = "512"]
+#![cfg_attr(docsrs, feature(doc_cfg))]
 
-extern crate proc_macro;
+mod runtime;
+#[cfg(feature = "context-stack")]
+mod stack;
 
-use proc_macro::TokenStream;
-use quote::{quote, quote_spanned};
-use syn::spanned::Spanned;
+#[cfg(feature = "context-stack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "context-stack")))]
+pub use stack::context_stack;
+
+#[cfg(feature = "tokio")]
+tokio::task_local! {
+    /// The correlation id of the request the current task is handling, for `correlation_id`.
+    ///
+    /// Set it with [`CORRELATION_ID.scope(id, future)`](tokio::task::LocalKey::scope) around the
+    /// future handling each request.
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub static CORRELATION_ID: String;
+}
 
 /// Use a doc comment to annotate the failure context of a function or try
 /// block.
 ///
+/// The lines of a multi-line doc comment are joined into a single message.
+///
+/// Works with `async fn` too: errors returned from the body, including those
+/// propagated from `.await?`, are tagged with the doc comment.
+///
 /// # Examples
 ///
 /// ```
 /// use context_attribute::context;
-/// use failure::{ensure, ResultExt};
+/// use failure::ensure;
 ///
 /// fn main() -> Result<(), failure::Error> {
 ///     let _ = square(2)?;
 ///     let _ = square(5)?;
-///     let _ = square(11)?;
+///     Ok(())
 /// }
 ///
 /// /// Square a number if it's less than 10.
 /// #[context]
-/// fn square(num: usize) -> Result<String, >{
+/// fn square(num: usize) -> Result<usize, failure::Error> {
 ///     ensure!(num < 10, "Number was larger than 10");
-///     num * num
+///     Ok(num * num)
 /// }
 /// ```
-#[proc_macro_attribute]
-pub fn context(_attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input = syn::parse_macro_input!(item as syn::ItemFn);
-
-    let attrs = &input.attrs;
-    let doc = attrs.iter().find(|attr| format!("{}", attr.path.segments.first().unwrap().value().ident) == "doc");
-    let doc = match doc {
-        Some(doc) => {
-            let mut iter = doc.clone().tts.into_iter().skip(1);
-            iter.next().unwrap()
-        },
-        None => return TokenStream::from(quote_spanned! {
-            input.span() => compile_error!("no doc comment provided")
-        }),
-    };
+///
+/// # Options
+///
+/// Options are passed as a comma-separated list, as in `#[context(id)]`. Unknown options are a
+/// compile error.
+///
+/// Options can be combined, as in `#[context(doc, args, location)]`, and the message is assembled
+/// in the same order no matter which order they're written in: the `id`, the `catalog` code, the
+/// `prefix`, the message itself, from the first of `catalog`, `t`, `msg`, `with`, and the doc
+/// comment that's set, and then the `attempt`, `args`, `location`, `thread`, and `correlation_id`
+/// suffixes, as in `[E#a1b2] storage: Read config.toml (attempt 2) (path = "config.toml") (at
+/// src/config.rs:12) (on thread main) (correlation id req-42)`. With `json`, the assembled message
+/// is the `context`.
+///
+/// - __`args`:__ append the function's arguments to the message, formatted with their `Debug`
+///   implementation, as in `Read config.toml (path = "config.toml")`. Like with `json`, the
+///   arguments can't be moved out of in the body.
+/// - __`attempt = attempts`:__ append the current attempt of a retry loop to the message, as in
+///   `Fetch the index (attempt 3)`. The expression is usually a function argument, and is
+///   evaluated when an error occurs, so it must be in scope in the function body and implement
+///   `Display`. Unlike an interpolated `{attempts}`, it doesn't have to appear in the doc comment.
+/// - __`batch`:__ for functions that process items in a `for` loop, give an error from a `?` in
+///   the loop a context with the index of its item, as in `Import the users: item 2`, instead of
+///   the function's. Only loops that are statements of the function body are rewritten, and `?`s
+///   in closures, `async` blocks, and macro invocations in them are left as-is, as are errors
+///   returned some other way, like with `bail!`, which get the function's context. Not supported
+///   on functions returning a future, or with `variant`.
+/// - __`catalog = "E1001"`:__ look the message up by an error code in a table of error codes,
+///   and prefix it with the code, as in `[E1001] Read the config`. Can't be combined with `msg`,
+///   `t`, `with`, or `doc`. See [Error codes](#error-codes).
+/// - __`correlation_id`:__ append the correlation id of the current request to the message, as in
+///   `Load the user (correlation id req-42)`. The id is read from [`CORRELATION_ID`], which needs
+///   the `tokio` feature. Use `correlation_id = path::to::function` to read it from elsewhere,
+///   with a function like `fn() -> Option<impl Display>`. Nothing is appended if it returns
+///   `None`. See [Correlation ids](#correlation-ids).
+/// - __`debug_msg = "message"`:__ use a different message in builds with `debug_assertions`
+///   enabled, such as `cargo build` without `--release`, so development builds can include
+///   internal details that production error output shouldn't, as in `#[context(debug_msg = "Load
+///   {path} with {config:?}", msg = "Load the config")]`. The message is picked with
+///   `cfg!(debug_assertions)` in the annotated crate, and both are checked in either profile. It's
+///   interpolated like `msg`, and the other message can come from `msg`, `t`, `with`, or the doc
+///   comment.
+/// - __`debug_only`:__ only attach the context in builds with `debug_assertions` enabled, such as
+///   `cargo build`, and call the function as if it wasn't annotated in release builds, for
+///   zero-overhead calls in production. The function's signature is the same either way, so call
+///   sites don't change. Errors in the attribute itself are reported in both.
+/// - __`default = "message"`:__ the message to use if the function has no doc comment, or an
+///   empty one, so undocumented functions don't need one. It's interpolated like a doc comment.
+///   The message comes from the first of these that applies: `catalog`, `t`, `msg`, or `with`,
+///   which replace the doc comment and so can't be combined with `default`, then the doc comment,
+///   then the default, and then the function name for an empty doc comment. Without any of them, a
+///   missing doc comment is a compile error.
+/// - __`doc`:__ use the doc comment as the message. This is the default, so it's only there to
+///   spell it out next to other options, and can't be combined with `msg`, `t`, `with`, or
+///   `catalog`.
+/// - __`eprintln`:__ print the error and its causes to stderr where it occurs, as in
+///   `error: Read address.txt` followed by `  caused by: ...` lines, while still returning it.
+///   This gives operators of long-running services immediate visibility without a logging
+///   framework. Nothing is printed on the success path.
+/// - __`err_if = "result == -1"`:__ turn `Ok` values that match a predicate into errors that get
+///   the context, to bridge sentinel values like those C functions return. The predicate is an
+///   expression in a string, with the `Ok` value bound as `result`. The error it's attached to
+///   says which predicate matched, and is converted into the error type with `From`. Can't be
+///   combined with `variant`.
+/// - __`errno`:__ for functions wrapping a C function that returns `-1` and sets `errno` on
+///   failure, or `GetLastError` on Windows. The body evaluates to the C function's return code, as
+///   in `unsafe { close(fd) }`, and the function returns it as `Ok`. On `-1` the error is read
+///   with [`std::io::Error::last_os_error`] as soon as the body returns, converted into the error
+///   type with `From`, and gets the context. The return type is `Result<T, E>`, where `T` is the
+///   C function's return type, such as `c_int`. Keep other calls that could overwrite `errno`
+///   after the C call out of the body. Not supported on `async` functions, and can't be combined
+///   with `variant` or `debug_only`. See `examples/errno.rs`.
+/// - __`id`:__ prefix the message with a short identifier, like `[E#a1b2] Read address.txt`. The
+///   identifier is a hash of the crate name, the source file relative to the crate's manifest,
+///   the type of the impl block or trait for methods, and the function name, so it stays the same
+///   between builds and machines and is unique enough to quote in bug reports. The macro only sees
+///   a method's type when the impl block or trait is annotated, so put `#[context(id)]` there;
+///   on a method with `self` on its own, it's an error.
+/// - __`join = "\n"`:__ the separator to join the lines of a multi-line doc comment with, a space
+///   by default. Blank lines are kept, so `join = "\n"` keeps paragraphs apart, as in verbose CLI
+///   errors.
+/// - __`json`:__ serialize the context as `{ "context": msg, "args": { ... }, "location":
+///   "file:line" }` for services that ship logs to JSON sinks. Arguments are formatted with their
+///   `Debug` implementation, so they must implement `Debug` and can't be moved out of in the body.
+///   Requires the `json` feature.
+/// - __`level = "warn"`:__ the level to label the error with when printing it with `eprintln`, as
+///   in `warn: Read address.txt`, so expected failures stand out less. One of `trace`, `debug`,
+///   `info`, `warn`, or `error`, the default. Requires `eprintln` or `or_else`.
+/// - __`literal`:__ use the doc comment verbatim, without interpolating placeholders, for doc
+///   comments that contain braces of their own, like `/// Expected a body like {"port": 80}`.
+/// - __`location`:__ append the location of the function to the message, as in `Read config.toml
+///   (at src/config.rs:12)`, to find where an error came from in logs without a backtrace.
+/// - __`map(Type => "message", ...)`:__ use a different message depending on the type of the
+///   error, e.g. `map(io::Error => "disk problem", ParseIntError => "bad number")`. The error's
+///   chain of causes is searched for each type in order, and the doc comment is used if none of
+///   them match. Messages can be interpolated like doc comments.
+/// - __`max_len = 120`:__ truncate the message to at most this many characters, ending it with
+///   `…` if it's cut, so long doc comments don't bloat logs. Only the message itself is
+///   truncated, so the `id` and `prefix` in front of it and the suffixes options like `args` add
+///   are kept whole. Doc comments without placeholders are truncated when compiling, and other
+///   messages when they're built.
+/// - __`msg = expr`:__ use an expression as the message instead of the doc comment, such as
+///   `msg = env!("SERVICE_NAME")` or `msg = concat!("Sync ", env!("CARGO_PKG_NAME"))`, so
+///   build-time configuration can label errors in multi-binary workspaces. String literals are
+///   interpolated like a doc comment, and other expressions are formatted with their `Display`
+///   implementation when an error occurs. Can't be used with `t`.
+/// - __`no_trim`:__ use the doc comment as-is. By default only the single leading space that `///`
+///   adds is stripped, so any further indentation is kept either way.
+/// - __`note = "message"`:__ add a note section to the error report, such as a suggestion for how
+///   to fix the error, as in `note = "check that {path} exists"`. It's interpolated like a doc
+///   comment. Notes are only shown when [`color-eyre`] is installed as the report handler.
+///   Requires the `eyre` feature.
+/// - __`notes`:__ use the first paragraph of the doc comment as the message, and add each of the
+///   following paragraphs as a note, so a doc comment's usual structure of a summary and details
+///   turns into a rich error report. Paragraphs are separated by blank lines, and interpolated like
+///   the message. Run `cargo run --example eyre --features eyre -- audit` to see the output.
+///   Requires the `eyre` feature, and only works with `eyre::Report`s like `note`. Without it, the
+///   paragraphs are joined into the message.
+/// - __`on_ok = path::to::function`:__ call a function with the message when the function
+///   doesn't return an error, e.g. for audit logging of successful operations. It's called as
+///   `function(&msg)` with a `&str`. Note that this gives the annotated function a side effect on
+///   its success path; the error path is unchanged. A `None` has no error to attach a message to,
+///   so it counts as a success for functions returning an `Option`.
+/// - __`optional_doc`:__ leave functions without a doc comment unchanged, instead of reporting an
+///   error, so a `#[context(optional_doc)]` on a trait only adds a context to the methods that are
+///   documented. Empty doc comments, and functions with a `msg`, `t`, `with`, `catalog`, or
+///   `default`, are handled as usual. `require_context!` still accepts these functions, since
+///   they're annotated.
+/// - __`or_else = expr`:__ return `Ok(expr)` instead of the error, for failures that should
+///   degrade gracefully to a default. The error isn't lost: with the context attached, it's
+///   printed to stderr like with `eprintln`, at the `warn` level unless `level` says otherwise,
+///   and `send_to` can record it elsewhere. The expression is only evaluated when an error
+///   occurs.
+/// - __`os_error`:__ append the raw OS error code when the error was caused by an
+///   [`std::io::Error`] that has one, as in `Read config.toml (os error 2)`, to compare failures
+///   across platforms. Other errors leave the message unchanged. Can't be combined with
+///   `variant`.
+/// - __`prefix = "category"`:__ prefix the message with a category, like `storage: Read
+///   address.txt`. To enforce a taxonomy of error messages, set the `CONTEXT_ATTRIBUTE_PREFIXES`
+///   environment variable to a comma-separated list of allowed categories when building, such as
+///   `storage,network`, and other categories are a compile error. Without it, any category is
+///   allowed. Cargo doesn't rebuild when the variable changes, so change it along with a clean
+///   build.
+/// - __`send_to = ERRORS`:__ send the message to a channel when the function fails, for systems
+///   that collect errors centrally, such as actors or pipelines. `ERRORS` is a path to a
+///   [`ContextSender`], like a `std::sync::mpsc::Sender<String>` or a `static` `OnceLock` of one,
+///   which can also be an argument. Sending never blocks, and a message that can't be sent right
+///   away is dropped. The message is sent as it's attached, so without the error `sep` adds.
+/// - __`sep = " -> "`:__ follow the message with a separator and the error it's attached to, so
+///   that displaying the error renders the whole chain, as in `Read config -> No such file`,
+///   e.g. to match an existing log format. The error's causes are unchanged. Requires an error
+///   that implements `Display`, so it can't be used on functions returning an `Option` or a type
+///   implementing `WithContext`.
+/// - __`skip`:__ leave the item as-is, to exclude a method from an annotated trait or impl block.
+///   See [Traits and impl blocks](#traits-and-impl-blocks).
+/// - __`summary`:__ only use the first sentence of the doc comment, so errors stay terse while
+///   the docs go into detail. The sentence ends at the first `.` followed by whitespace, so
+///   abbreviations such as `e.g. this` end it early.
+/// - __`t = "key"`:__ look the message up through a translation function instead of using the
+///   doc comment, for localized errors. The function is called with the key when an error occurs,
+///   and returns anything that converts into a `Cow<'static, str>`, such as a `String` or a
+///   `&'static str`. Keys must be non-empty and consist of letters, digits, `.`, `_`, `-`, and `:`.
+/// - __`t_fn = path::to::tr`:__ the translation function to use with `t`. Defaults to whichever
+///   `tr` function is in scope.
+/// - __`thread`:__ append the name of the current thread to the message, as in `Read address.txt
+///   (on thread worker-1)`, or its id if it doesn't have a name, to correlate errors with worker
+///   threads in logs. Like the rest of the message, it's only looked up when an error occurs.
+/// - __`variant = Enum::Variant`:__ wrap the error in a variant of an error enum, such as one
+///   derived with [`thiserror`], instead of attaching a context. The variant is called with the
+///   message and the error, so it should look like `Io(String, #[source] io::Error)`. The error
+///   type of the body is inferred from the variant, so `?` converts into `io::Error` here. Requires
+///   a return type of the form `Result<T, Enum>`, and can't be used with `map` or on
+///   `#[async_trait]` methods. See `examples/thiserror.rs`.
+/// - __`variant = Enum::Variant { field, other: expr }`:__ wrap the error in a struct-like variant
+///   instead, such as `Io { path: PathBuf, source: io::Error }`. The error goes in its `source`
+///   field, and the other fields are set as written when an error occurs, after the body has run,
+///   so they can use the function's arguments. A `context` field written on its own, as in
+///   `Enum::Io { path, context }`, gets the message, which is left out otherwise.
+/// - __`verbose`:__ only attach the context if the `CONTEXT_VERBOSE` environment variable is set
+///   to anything other than `0` or an empty string when the program runs, and return the error
+///   as-is otherwise, so operators can turn on detailed errors in the field without recompiling.
+///   The variable is read once, the first time an annotated function returns, and cached for the
+///   rest of the run, so it can't be toggled while the program is running. Can't be used with
+///   `variant` or `on_ok`.
+/// - __`with = self.describe_error`:__ call a method on `self` for the message instead of using
+///   the doc comment, so stateful objects can describe their errors from their fields. The method
+///   takes `&self` and returns anything that converts into a `Cow<'static, str>`, like a `String`
+///   or a `&'static str`, as in `fn describe_error(&self) -> String`. It's only called when an
+///   error occurs, after the body has run, so it sees the object's state at that point. Can only be
+///   used on methods, and can't be used with `msg`, `t`, or `doc`.
+///
+/// [`thiserror`]: https://docs.rs/thiserror
+/// [`color-eyre`]: https://docs.rs/color-eyre
+pub use context_attribute_macros::context;
 
-    let vis = &input.vis;
-    let constness = &input.constness;
-    let unsafety = &input.unsafety;
-    let asyncness = &input.asyncness;
-    let abi = &input.abi;
+/// Require `#[context]` on every public function returning a `Result` in a module.
+///
+/// Wrap an inline module in `require_context!` to make it a compile error to add a public function
+/// returning a `Result` without a context, for teams that want to enforce context hygiene. Public
+/// methods in the module's impl blocks and the contents of nested inline modules are checked too,
+/// and methods in an impl block that has `#[context]` itself count as annotated. Modules declared
+/// in other files, as in `mod api;`, can't be seen by the macro, so wrap their contents in an
+/// inline module instead.
+///
+/// # Examples
+///
+/// ```
+/// use context_attribute::require_context;
+///
+/// require_context! {
+///     pub mod api {
+///         use context_attribute::context;
+///
+///         /// Read the config
+///         #[context]
+///         pub fn read_config() -> Result<String, failure::Error> {
+///             Ok(std::fs::read_to_string("config.toml")?)
+///         }
+///
+///         // Not public, so it doesn't need a context.
+///         fn parse(input: &str) -> Result<usize, failure::Error> {
+///             Ok(input.parse()?)
+///         }
+///     }
+/// }
+/// ```
+pub use context_attribute_macros::require_context;
 
-    let generics = &input.decl.generics;
-    let name = &input.ident;
-    let inputs = &input.decl.inputs;
-    let output = &input.decl.output;
-    let body = &input.block.stmts;
+pub use runtime::{ContextSender, WithContext};
 
-    let args: Vec<syn::Pat> = inputs.pairs().filter_map(|pair| {
-        match pair.into_value() {
-            syn::FnArg::Captured(arg) => Some(arg.pat.clone()),
-            _ => return None,
+/// Attach a context to the output of a future, such as an `async` block.
+///
+/// Attributes can't be put on expressions, so this fills the gap for ad-hoc futures that
+/// `#[context]` can't reach. It evaluates to a future resolving to the future's output with the
+/// context attached, if it was an error. The message can be anything that converts into a
+/// `Cow<'static, str>`, and is only evaluated when an error occurs, so `format!(...)` is free on
+/// the success path.
+///
+/// Unlike with `#[context]`, the error type can't be inferred from a return type, so `async`
+/// blocks that use `?` need to spell it out, as in `Ok::<_, failure::Error>(...)`. Doc comment
+/// interpolation and the attribute's options aren't available either.
+///
+/// # Examples
+///
+/// ```
+/// use context_attribute::context_async;
+/// use futures::executor::block_on;
+///
+/// let id = 12;
+/// let future = context_async!(format!("Fetch user {}", id), async {
+///     let user: usize = "twelve".parse()?;
+///     Ok::<_, failure::Error>(user)
+/// });
+/// let err = block_on(future).unwrap_err();
+/// assert_eq!(err.to_string(), "Fetch user 12");
+/// ```
+#[macro_export]
+macro_rules! context_async {
+    ($message:expr, $future:expr $(,)?) => {
+        async {
+            $crate::__private::Contextable::apply_context($future.await, || {
+                $crate::__private::Message::from($message)
+            })
         }
-    }).collect();
+    };
+}
 
-    let result = quote! {
-        #(#attrs)*
-        #vis #constness #unsafety #asyncness #abi fn #generics #name(#(#inputs)*) #output {
-            #constness #unsafety #asyncness #abi fn #generics #name(#(#inputs)*) #output {
-                #(#body)*
-            }
-            Ok(#name(#(#args)*).context(#doc.trim())?)
+/// Attach a context to the result of a closure every time it's called.
+///
+/// This is for closures that are stored instead of called right away, like the handlers in a
+/// command dispatch table, which `#[context]` can't reach. It evaluates to a closure with the same
+/// arguments and return type as the one passed in: `context_closure!(msg, |a: A, b: B| -> R
+/// { ... })` is an `impl Fn(A, B) -> R`, or an `FnMut` or `FnOnce` if the closure passed in is one.
+/// Calling it calls the closure, and attaches the context to the result if it's an error. The
+/// message is evaluated each time an error occurs, like with [`context_async!`].
+///
+/// Arguments must be plain identifiers, optionally with a type. The return type usually needs to
+/// be spelled out for `?` to know the error type, and argument types for calling methods on the
+/// arguments, as with any closure that isn't called in place. Closures can be `move`.
+///
+/// # Examples
+///
+/// ```
+/// use context_attribute::context_closure;
+///
+/// use failure::Error;
+/// use std::collections::HashMap;
+///
+/// let mut commands: HashMap<&str, Box<dyn Fn(&str) -> Result<usize, Error>>> = HashMap::new();
+/// commands.insert(
+///     "len",
+///     Box::new(context_closure!("Run the len command", |arg: &str| -> Result<usize, Error> {
+///         Ok(arg.len())
+///     })),
+/// );
+/// commands.insert(
+///     "parse",
+///     Box::new(context_closure!("Run the parse command", |arg: &str| -> Result<usize, Error> {
+///         Ok(arg.parse()?)
+///     })),
+/// );
+/// let parse = &commands["parse"];
+/// assert_eq!(parse("12").unwrap(), 12);
+/// assert_eq!(parse("twelve").unwrap_err().to_string(), "Run the parse command");
+/// ```
+#[macro_export]
+macro_rules! context_closure {
+    (@wrap $message:expr, $inner:ident, $($arg:ident $(: $ty:ty)?),*) => {
+        move |$($arg $(: $ty)?),*| {
+            $crate::__private::Contextable::apply_context($inner($($arg),*), || {
+                $crate::__private::Message::from($message)
+            })
         }
     };
+    ($message:expr, move || $($body:tt)+) => {
+        $crate::context_closure!($message, move | | $($body)+)
+    };
+    ($message:expr, || $($body:tt)+) => {
+        $crate::context_closure!($message, | | $($body)+)
+    };
+    ($message:expr, move |$($arg:ident $(: $ty:ty)?),* $(,)?| $($body:tt)+) => {{
+        #[allow(unused_mut)]
+        let mut inner = move |$($arg $(: $ty)?),*| $($body)+;
+        $crate::context_closure!(@wrap $message, inner, $($arg $(: $ty)?),*)
+    }};
+    ($message:expr, |$($arg:ident $(: $ty:ty)?),* $(,)?| $($body:tt)+) => {{
+        #[allow(unused_mut)]
+        let mut inner = |$($arg $(: $ty)?),*| $($body)+;
+        $crate::context_closure!(@wrap $message, inner, $($arg $(: $ty)?),*)
+    }};
+}
 
-    result.into()
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::runtime::*;
+    #[cfg(feature = "context-stack")]
+    pub use crate::stack::{enter, scope};
 }
@@ -0,0 +1,710 @@
+//! Runtime support for the code generated by `#[context]`.
+
+use std::borrow::Cow;
+#[cfg(any(
+    feature = "failure",
+    feature = "anyhow",
+    feature = "eyre",
+    feature = "miette",
+    feature = "error-stack"
+))]
+use std::ops::ControlFlow;
+
+/// A context message.
+///
+/// Doc comments are `&'static str` literals and are passed as `Cow::Borrowed` without
+/// allocating. Messages computed at runtime are passed as `Cow::Owned`.
+pub type Message = Cow<'static, str>;
+
+/// A return type that a context message can be attached to.
+///
+/// This is implemented for `Result`s whose error type belongs to one of the enabled backends, so
+/// the generated code doesn't have to know which backend is in use, for `ControlFlow`s that break
+/// with such an error, and for `Option`s, which have no error to attach a context to and are
+/// passed through as-is. Other types opt in by
+/// implementing [`WithContext`] instead.
+#[diagnostic::on_unimplemented(
+    message = "#[context] can't attach a context to `{Self}`",
+    label = "this return type doesn't support context",
+    note = "the function must return an `Option`, a `Result` or `ControlFlow` whose error type is one of the enabled backends, such as `failure::Error`, or a type implementing `WithContext`",
+    note = "for a generic or associated error type, constrain it, as in `S: Service<Error = failure::Error>`"
+)]
+pub trait Contextable: Sized {
+    /// The error the context is attached to.
+    type Error;
+
+    /// Attach a context message to the error, if there is one.
+    ///
+    /// The message is computed lazily, so it costs nothing on the success path.
+    #[track_caller]
+    fn apply_context<F>(self, f: F) -> Self
+    where
+        F: FnOnce() -> Message,
+    {
+        self.apply_context_with(|_| f())
+    }
+
+    /// Attach a context message computed from the error, if there is one.
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message;
+
+    /// Print the error and its causes to stderr at a level like `error`, if there is an error.
+    fn eprint(&self, level: &str);
+}
+
+#[cfg(feature = "failure")]
+impl<T> Contextable for Result<T, failure::Error> {
+    type Error = failure::Error;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        self.map_err(|err| {
+            let msg = f(&err);
+            err.context(msg).into()
+        })
+    }
+
+    fn eprint(&self, level: &str) {
+        if let Err(err) = self {
+            eprint(level, err.iter_chain());
+        }
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl<T> Contextable for Result<T, anyhow::Error> {
+    type Error = anyhow::Error;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        // Pass the same context types as `.context("...")` and `.with_context(|| format!(...))`,
+        // so literals don't allocate and the context can be downcast like a hand-written one.
+        self.map_err(|err| match f(&err) {
+            Cow::Borrowed(msg) => err.context(msg),
+            Cow::Owned(msg) => err.context(msg),
+        })
+    }
+
+    fn eprint(&self, level: &str) {
+        if let Err(err) = self {
+            eprint(level, err.chain());
+        }
+    }
+}
+
+#[cfg(feature = "eyre")]
+impl<T> Contextable for Result<T, eyre::Report> {
+    type Error = eyre::Report;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        self.map_err(|err| {
+            let msg = f(&err);
+            err.wrap_err(msg)
+        })
+    }
+
+    fn eprint(&self, level: &str) {
+        if let Err(err) = self {
+            eprint(level, err.chain());
+        }
+    }
+}
+
+#[cfg(feature = "failure")]
+impl<C> Contextable for ControlFlow<failure::Error, C> {
+    type Error = failure::Error;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        match self {
+            ControlFlow::Break(err) => {
+                let msg = f(&err);
+                ControlFlow::Break(err.context(msg).into())
+            }
+            next => next,
+        }
+    }
+
+    fn eprint(&self, level: &str) {
+        if let ControlFlow::Break(err) = self {
+            eprint(level, err.iter_chain());
+        }
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl<C> Contextable for ControlFlow<anyhow::Error, C> {
+    type Error = anyhow::Error;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        match self {
+            ControlFlow::Break(err) => ControlFlow::Break(match f(&err) {
+                Cow::Borrowed(msg) => err.context(msg),
+                Cow::Owned(msg) => err.context(msg),
+            }),
+            next => next,
+        }
+    }
+
+    fn eprint(&self, level: &str) {
+        if let ControlFlow::Break(err) = self {
+            eprint(level, err.chain());
+        }
+    }
+}
+
+#[cfg(feature = "eyre")]
+impl<C> Contextable for ControlFlow<eyre::Report, C> {
+    type Error = eyre::Report;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        match self {
+            ControlFlow::Break(err) => {
+                let msg = f(&err);
+                ControlFlow::Break(err.wrap_err(msg))
+            }
+            next => next,
+        }
+    }
+
+    fn eprint(&self, level: &str) {
+        if let ControlFlow::Break(err) = self {
+            eprint(level, err.chain());
+        }
+    }
+}
+
+#[cfg(feature = "miette")]
+impl<T> Contextable for Result<T, miette::Report> {
+    type Error = miette::Report;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        self.map_err(|err| {
+            let msg = f(&err);
+            err.wrap_err(msg)
+        })
+    }
+
+    fn eprint(&self, level: &str) {
+        if let Err(err) = self {
+            eprint(level, err.chain());
+        }
+    }
+}
+
+#[cfg(feature = "miette")]
+impl<C> Contextable for ControlFlow<miette::Report, C> {
+    type Error = miette::Report;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        match self {
+            ControlFlow::Break(err) => {
+                let msg = f(&err);
+                ControlFlow::Break(err.wrap_err(msg))
+            }
+            next => next,
+        }
+    }
+
+    fn eprint(&self, level: &str) {
+        if let ControlFlow::Break(err) = self {
+            eprint(level, err.chain());
+        }
+    }
+}
+
+// `syn::Error`s have no causes to keep the error in, so the message is prepended to each of the
+// errors it holds instead, which keeps their spans.
+#[cfg(feature = "syn")]
+impl<T> Contextable for Result<T, syn::Error> {
+    type Error = syn::Error;
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        self.map_err(|err| {
+            let msg = f(&err);
+            err.into_iter()
+                .map(|err| syn::Error::new(err.span(), format!("{}: {}", msg, err)))
+                .reduce(|mut errors, err| {
+                    errors.combine(err);
+                    errors
+                })
+                .expect("a syn::Error holds at least one error")
+        })
+    }
+
+    fn eprint(&self, level: &str) {
+        if let Err(err) = self {
+            eprint(level, std::iter::once(err));
+        }
+    }
+}
+
+// Attaching the message keeps the report's context type, where `change_context` would need a type
+// for every message. Reports record where each attachment is made, so keep the caller's location.
+#[cfg(feature = "error-stack")]
+impl<T, C> Contextable for Result<T, error_stack::Report<C>> {
+    type Error = error_stack::Report<C>;
+
+    #[track_caller]
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        match self {
+            Err(err) => {
+                let msg = f(&err);
+                Err(err.attach(msg))
+            }
+            ok => ok,
+        }
+    }
+
+    fn eprint(&self, level: &str) {
+        if let Err(err) = self {
+            eprint(level, frames(err));
+        }
+    }
+}
+
+#[cfg(feature = "error-stack")]
+impl<B, C> Contextable for ControlFlow<error_stack::Report<B>, C> {
+    type Error = error_stack::Report<B>;
+
+    #[track_caller]
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        match self {
+            ControlFlow::Break(err) => {
+                let msg = f(&err);
+                ControlFlow::Break(err.attach(msg))
+            }
+            next => next,
+        }
+    }
+
+    fn eprint(&self, level: &str) {
+        if let ControlFlow::Break(err) = self {
+            eprint(level, frames(err));
+        }
+    }
+}
+
+/// Get the contexts and printable attachments of a report, most recent first.
+#[cfg(feature = "error-stack")]
+fn frames<C>(report: &error_stack::Report<C>) -> impl Iterator<Item = String> + '_ {
+    use error_stack::{AttachmentKind, FrameKind};
+    report.frames().filter_map(|frame| match frame.kind() {
+        FrameKind::Context(context) => Some(context.to_string()),
+        FrameKind::Attachment(AttachmentKind::Printable(attachment)) => {
+            Some(attachment.to_string())
+        }
+        _ => None,
+    })
+}
+
+impl<T> Contextable for Option<T> {
+    type Error = ();
+
+    fn apply_context_with<F>(self, _: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        self
+    }
+
+    fn eprint(&self, _: &str) {}
+}
+
+/// A result-like type that a context message can be attached to.
+///
+/// Implement this for custom types, such as ones that work with `?`, to use them as the return
+/// type of a function annotated with `#[context]`. Only the message is passed to the type, so
+/// `map` and `eprintln` don't have an error to look at, and print nothing respectively.
+///
+/// # Examples
+///
+/// ```
+/// use context_attribute::{context, WithContext};
+/// use std::borrow::Cow;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Outcome {
+///     Done(usize),
+///     Failed(Vec<String>),
+/// }
+///
+/// impl WithContext for Outcome {
+///     fn with_context<F>(self, f: F) -> Self
+///     where
+///         F: FnOnce() -> Cow<'static, str>,
+///     {
+///         match self {
+///             Outcome::Failed(mut messages) => {
+///                 messages.push(f().into_owned());
+///                 Outcome::Failed(messages)
+///             }
+///             done => done,
+///         }
+///     }
+/// }
+///
+/// /// Count the widgets
+/// #[context]
+/// fn count(fail: bool) -> Outcome {
+///     match fail {
+///         true => Outcome::Failed(vec!["out of widgets".into()]),
+///         false => Outcome::Done(3),
+///     }
+/// }
+///
+/// assert_eq!(count(false), Outcome::Done(3));
+/// assert_eq!(count(true), Outcome::Failed(vec!["out of widgets".into(), "Count the widgets".into()]));
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "#[context] can't attach a context to `{Self}`",
+    label = "this return type doesn't support context",
+    note = "the function must return an `Option`, a `Result` or `ControlFlow` whose error type is one of the enabled backends, such as `failure::Error`, or a type implementing `WithContext`",
+    note = "for a generic or associated error type, constrain it, as in `S: Service<Error = failure::Error>`"
+)]
+pub trait WithContext: Sized {
+    /// Attach a context message, if this represents an error.
+    ///
+    /// The message should only be computed on the error path.
+    fn with_context<F>(self, f: F) -> Self
+    where
+        F: FnOnce() -> Cow<'static, str>;
+}
+
+impl<T: WithContext> Contextable for T {
+    type Error = ();
+
+    fn apply_context_with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Self::Error) -> Message,
+    {
+        self.with_context(|| f(&()))
+    }
+
+    fn eprint(&self, _: &str) {}
+}
+
+/// A channel that `#[context(send_to = ...)]` sends the messages of failing functions to.
+///
+/// This is implemented for the senders of `std::sync::mpsc` channels of `String`s, and for
+/// `OnceLock`s of them, so a channel can be set up at runtime and stored in a `static`. Sending
+/// must not block, so messages that can't be sent right away, like when the channel is full or
+/// disconnected, are dropped. Other channels, such as async ones, can implement this to be used
+/// with `send_to` too.
+///
+/// # Examples
+///
+/// ```
+/// use context_attribute::context;
+/// use std::sync::{mpsc, OnceLock};
+///
+/// static ERRORS: OnceLock<mpsc::Sender<String>> = OnceLock::new();
+///
+/// /// Sync the inbox
+/// #[context(send_to = ERRORS)]
+/// fn sync() -> Result<(), failure::Error> {
+///     failure::bail!("connection reset")
+/// }
+///
+/// let (sender, receiver) = mpsc::channel();
+/// ERRORS.set(sender).unwrap();
+/// assert!(sync().is_err());
+/// assert_eq!(receiver.try_recv().unwrap(), "Sync the inbox");
+/// ```
+pub trait ContextSender {
+    /// Send a message, or drop it if it can't be sent without blocking.
+    fn send_context(&self, message: String);
+}
+
+impl ContextSender for std::sync::mpsc::Sender<String> {
+    fn send_context(&self, message: String) {
+        let _ = self.send(message);
+    }
+}
+
+impl ContextSender for std::sync::mpsc::SyncSender<String> {
+    fn send_context(&self, message: String) {
+        let _ = self.try_send(message);
+    }
+}
+
+impl<T: ContextSender> ContextSender for std::sync::OnceLock<T> {
+    fn send_context(&self, message: String) {
+        if let Some(sender) = self.get() {
+            sender.send_context(message);
+        }
+    }
+}
+
+impl<T: ContextSender + ?Sized> ContextSender for &T {
+    fn send_context(&self, message: String) {
+        (**self).send_context(message);
+    }
+}
+
+/// Send a message to a channel, and return it to be attached to the error.
+pub fn send<S>(sender: &S, message: Message) -> Message
+where
+    S: ContextSender + ?Sized,
+{
+    sender.send_context(message.to_string());
+    message
+}
+
+/// Call the closure that holds the body of a function.
+pub fn call<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    f()
+}
+
+/// Turn the return code of a C function into a result, as in `#[context(errno)]`.
+///
+/// `-1` is an error, read from `errno`, or `GetLastError` on Windows, right away so nothing can
+/// overwrite it. Any other code is returned as-is.
+pub fn errno<T, E>(code: T) -> Result<T, E>
+where
+    T: PartialEq + From<i8>,
+    E: From<std::io::Error>,
+{
+    match code == T::from(-1) {
+        true => Err(std::io::Error::last_os_error().into()),
+        false => Ok(code),
+    }
+}
+
+/// The error for an `Ok` value that matched the predicate of `#[context(err_if = "...")]`.
+#[derive(Debug)]
+pub struct Sentinel(pub &'static str);
+
+impl std::fmt::Display for Sentinel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the function returned a value matching `{}`", self.0)
+    }
+}
+
+impl std::error::Error for Sentinel {}
+
+/// Print an error and its causes to stderr, one per line, with the error labeled by a level like
+/// `error`.
+pub fn eprint<I>(level: &str, mut chain: I)
+where
+    I: Iterator,
+    I::Item: std::fmt::Display,
+{
+    if let Some(err) = chain.next() {
+        eprintln!("{}: {}", level, err);
+    }
+    for cause in chain {
+        eprintln!("  caused by: {}", cause);
+    }
+}
+
+/// An error whose chain of causes can be searched by type.
+pub trait Downcast {
+    /// The first of the error and its causes that's of type `T`.
+    fn find_cause<T>(&self) -> Option<&T>
+    where
+        T: std::error::Error + Send + Sync + 'static;
+
+    /// Whether the error, or any of its causes, is of type `T`.
+    fn is_caused_by<T>(&self) -> bool
+    where
+        T: std::error::Error + Send + Sync + 'static,
+    {
+        self.find_cause::<T>().is_some()
+    }
+}
+
+#[cfg(feature = "failure")]
+impl Downcast for failure::Error {
+    fn find_cause<T>(&self) -> Option<&T>
+    where
+        T: std::error::Error + Send + Sync + 'static,
+    {
+        self.iter_chain()
+            .find_map(|cause| cause.downcast_ref::<T>())
+    }
+}
+
+#[cfg(feature = "eyre")]
+impl Downcast for eyre::Report {
+    fn find_cause<T>(&self) -> Option<&T>
+    where
+        T: std::error::Error + Send + Sync + 'static,
+    {
+        self.chain().find_map(|cause| cause.downcast_ref::<T>())
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl Downcast for anyhow::Error {
+    fn find_cause<T>(&self) -> Option<&T>
+    where
+        T: std::error::Error + Send + Sync + 'static,
+    {
+        self.chain().find_map(|cause| cause.downcast_ref::<T>())
+    }
+}
+
+#[cfg(feature = "miette")]
+impl Downcast for miette::Report {
+    fn find_cause<T>(&self) -> Option<&T>
+    where
+        T: std::error::Error + Send + Sync + 'static,
+    {
+        self.chain().find_map(|cause| cause.downcast_ref::<T>())
+    }
+}
+
+#[cfg(feature = "error-stack")]
+impl<C> Downcast for error_stack::Report<C> {
+    fn find_cause<T>(&self) -> Option<&T>
+    where
+        T: std::error::Error + Send + Sync + 'static,
+    {
+        self.downcast_ref::<T>()
+    }
+}
+
+#[cfg(feature = "syn")]
+impl Downcast for syn::Error {
+    fn find_cause<T>(&self) -> Option<&T>
+    where
+        T: std::error::Error + Send + Sync + 'static,
+    {
+        (self as &(dyn std::error::Error + 'static)).downcast_ref::<T>()
+    }
+}
+
+/// Follow a message with the raw OS error code of the first `io::Error` among the error and its
+/// causes, as in `Read config (os error 2)`. Other errors leave the message unchanged.
+pub fn os_error<E>(message: Message, err: &E) -> Message
+where
+    E: Downcast + ?Sized,
+{
+    match err
+        .find_cause::<std::io::Error>()
+        .and_then(std::io::Error::raw_os_error)
+    {
+        Some(code) => Message::Owned(format!("{} (os error {})", message, code)),
+        None => message,
+    }
+}
+
+/// Add a note section to an eyre error report.
+///
+/// Notes are only shown when `color-eyre` is installed as the report handler.
+#[cfg(feature = "eyre")]
+pub fn note<T, F>(result: Result<T, eyre::Report>, f: F) -> Result<T, eyre::Report>
+where
+    F: FnOnce() -> Message,
+{
+    use color_eyre::Section;
+    result.map_err(|err| err.note(f()))
+}
+
+/// Whether contexts with the `verbose` option should be attached.
+///
+/// This is the case if the `CONTEXT_VERBOSE` environment variable is set to anything other than
+/// `0` or an empty string. It's only read once, the first time it's needed.
+pub fn verbose() -> bool {
+    static VERBOSE: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *VERBOSE.get_or_init(|| {
+        std::env::var_os("CONTEXT_VERBOSE")
+            .is_some_and(|verbose| verbose != "0" && !verbose.is_empty())
+    })
+}
+
+/// Append a correlation id to a message, if there is one.
+pub fn correlated<D>(message: Message, id: Option<D>) -> Message
+where
+    D: std::fmt::Display,
+{
+    match id {
+        Some(id) => Message::Owned(format!("{} (correlation id {})", message, id)),
+        None => message,
+    }
+}
+
+/// Get the correlation id of the current task, if it's in a `CORRELATION_ID` scope.
+#[cfg(feature = "tokio")]
+pub fn correlation_id() -> Option<String> {
+    crate::CORRELATION_ID.try_with(String::clone).ok()
+}
+
+/// Truncate a message to at most `max` characters, ending it with `…` if it's cut.
+pub fn truncate(message: Message, max: usize) -> Message {
+    if message.chars().nth(max).is_none() {
+        return message;
+    }
+    let text: String = message.chars().take(max.saturating_sub(1)).collect();
+    Message::Owned(format!("{}…", text))
+}
+
+/// Append the name of the current thread to a message, or its id if it doesn't have a name.
+pub fn thread(message: Message) -> Message {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => Message::Owned(format!("{} (on thread {})", message, name)),
+        None => Message::Owned(format!("{} (on thread {:?})", message, thread.id())),
+    }
+}
+
+/// Follow a message with a separator and the error it's attached to, as in `Read config -> No
+/// such file`.
+pub fn separated<E>(message: Message, sep: &str, err: &E) -> Message
+where
+    E: std::fmt::Display + ?Sized,
+{
+    Message::Owned(format!("{}{}{}", message, sep, err))
+}
+
+/// Serialize a message as `{ "context": ..., "args": { ... }, "location": "file:line" }`.
+///
+/// Arguments are formatted with their `Debug` implementation.
+#[cfg(feature = "json")]
+pub fn json(message: Message, args: &[(&str, &dyn std::fmt::Debug)], location: &str) -> Message {
+    let args: serde_json::Map<String, serde_json::Value> = args
+        .iter()
+        .map(|(name, value)| (name.to_string(), format!("{:?}", value).into()))
+        .collect();
+    let json = serde_json::json!({
+        "context": message,
+        "args": args,
+        "location": location,
+    });
+    Message::Owned(json.to_string())
+}
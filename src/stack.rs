@@ -0,0 +1,108 @@
+//! A stack of the contexts that are currently entered.
+
+use crate::runtime::Message;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+thread_local! {
+    static STACK: RefCell<Vec<Message>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Get the contexts of the annotated functions currently being executed, outermost first.
+///
+/// Contexts are pushed when an annotated function is entered and popped when it returns. Annotated
+/// `async fn`s push their context each time they're polled, so the stack reflects the logical call
+/// path of the current task even across `.await` points and threads.
+///
+/// # Examples
+///
+/// ```
+/// use context_attribute::{context, context_stack};
+///
+/// /// Load the config
+/// #[context]
+/// fn load() -> Result<(), failure::Error> {
+///     parse()
+/// }
+///
+/// /// Parse the config
+/// #[context]
+/// fn parse() -> Result<(), failure::Error> {
+///     let stack = context_stack();
+///     failure::ensure!(stack == ["Load the config", "Parse the config"], "unexpected stack {:?}", stack);
+///     Ok(())
+/// }
+///
+/// load().unwrap();
+/// assert!(context_stack().is_empty());
+/// ```
+pub fn context_stack() -> Vec<Message> {
+    STACK.with(|stack| stack.borrow().clone())
+}
+
+fn push(message: Message) {
+    STACK.with(|stack| stack.borrow_mut().push(message));
+}
+
+fn pop() -> Option<Message> {
+    STACK.with(|stack| stack.borrow_mut().pop())
+}
+
+/// Pops its context off the stack when dropped.
+#[derive(Debug)]
+pub struct Entered {
+    _private: (),
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        pop();
+    }
+}
+
+/// Push a context onto the stack until the returned guard is dropped.
+pub fn enter<F>(f: F) -> Entered
+where
+    F: FnOnce() -> Message,
+{
+    push(f());
+    Entered { _private: () }
+}
+
+/// A future that pushes its context onto the stack every time it's polled.
+///
+/// The inner future is boxed so it can be polled without `unsafe` pin projections.
+#[derive(Debug)]
+pub struct Scope<F> {
+    future: Pin<Box<F>>,
+    message: Option<Message>,
+}
+
+impl<F: Future> Future for Scope<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        push(this.message.take().expect("polled after completion"));
+        // Keep the stack balanced if the future panics, but take the message back otherwise.
+        let entered = Entered { _private: () };
+        let poll = this.future.as_mut().poll(cx);
+        std::mem::forget(entered);
+        this.message = pop();
+        poll
+    }
+}
+
+/// Push a context onto the stack whenever the future is polled.
+pub fn scope<F, M>(future: F, f: M) -> Scope<F>
+where
+    F: Future,
+    M: FnOnce() -> Message,
+{
+    Scope {
+        future: Box::pin(future),
+        message: Some(f()),
+    }
+}
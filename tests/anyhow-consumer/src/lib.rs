@@ -0,0 +1,21 @@
+//! A crate that only depends on `anyhow`, to check the generated code doesn't need `failure`.
+
+use anyhow::{ensure, Result};
+use context_attribute::context;
+
+/// Square a number if it's less than 10.
+#[context]
+pub fn square(num: usize) -> Result<usize> {
+    ensure!(num < 10, "Number was too large");
+    Ok(num * num)
+}
+
+#[test]
+fn square_with_context() {
+    assert_eq!(square(3).unwrap(), 9);
+    let err = square(12).unwrap_err();
+    assert_eq!(
+        format!("{:#}", err),
+        "Square a number if it's less than 10.: Number was too large"
+    );
+}
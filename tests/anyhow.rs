@@ -0,0 +1,111 @@
+#![cfg(feature = "anyhow")]
+
+use anyhow::{bail, Result};
+use context_attribute::context;
+
+/// Read {path} from disk
+#[context]
+fn read(path: &str) -> Result<String> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Check the number {num}
+#[context]
+fn check(num: usize) -> anyhow::Result<usize> {
+    if num > 10 {
+        bail!("number was too large");
+    }
+    Ok(num)
+}
+
+#[test]
+fn anyhow_backend() {
+    let err = read("does-not-exist.txt").unwrap_err();
+    assert_eq!(err.to_string(), "Read does-not-exist.txt from disk");
+    assert!(err.root_cause().downcast_ref::<std::io::Error>().is_some());
+
+    assert_eq!(check(2).unwrap(), 2);
+    let causes: Vec<String> = check(12)
+        .unwrap_err()
+        .chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Check the number 12", "number was too large"]);
+}
+
+/// Load {input}
+#[context(map(std::io::Error => "disk problem", std::num::ParseIntError => "bad number"))]
+fn load(input: &str) -> Result<usize> {
+    match input {
+        "missing" => Ok(read("does-not-exist.txt")?.len()),
+        "other" => bail!("something else"),
+        input => Ok(input.parse()?),
+    }
+}
+
+#[test]
+fn map_error_types() {
+    assert_eq!(load("12").unwrap(), 12);
+    assert_eq!(load("missing").unwrap_err().to_string(), "disk problem");
+    assert_eq!(load("x").unwrap_err().to_string(), "bad number");
+    assert_eq!(load("other").unwrap_err().to_string(), "Load other");
+}
+
+/// Open the config
+#[context]
+fn open_config() -> Result<()> {
+    bail!("no such file");
+}
+
+#[test]
+fn context_types() {
+    let err = open_config().unwrap_err();
+    assert_eq!(err.downcast_ref::<&str>(), Some(&"Open the config"));
+    let err = check(12).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<String>().map(String::as_str),
+        Some("Check the number 12")
+    );
+}
+
+/// Run step {name}
+#[context]
+fn run_step<E>(name: &str, step: impl FnOnce() -> std::result::Result<usize, E>) -> Result<usize>
+where
+    E: Into<anyhow::Error>,
+{
+    step().map_err(Into::into)
+}
+
+#[test]
+fn generic_error_inputs() {
+    assert_eq!(run_step("one", || Ok::<_, std::fmt::Error>(1)).unwrap(), 1);
+    let err = run_step("two", || "x".parse::<usize>()).unwrap_err();
+    let causes: Vec<String> = err.chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(causes, ["Run step two", "invalid digit found in string"]);
+}
+
+/// Reserve {seats} seats
+#[context]
+fn reserve(seats: usize) -> Result<usize> {
+    if seats == 0 {
+        return Err(anyhow::anyhow!("boom"));
+    }
+    match seats > 4 {
+        true => Err(anyhow::anyhow!("only 4 seats left, asked for {}", seats)),
+        false => Ok(seats),
+    }
+}
+
+#[test]
+fn anyhow_macro_errors() {
+    assert_eq!(reserve(2).unwrap(), 2);
+    let causes = |err: anyhow::Error| -> Vec<String> {
+        err.chain().map(|cause| cause.to_string()).collect()
+    };
+    assert_eq!(causes(reserve(0).unwrap_err()), ["Reserve 0 seats", "boom"]);
+    assert_eq!(
+        causes(reserve(6).unwrap_err()),
+        ["Reserve 6 seats", "only 4 seats left, asked for 6"]
+    );
+}
@@ -0,0 +1,49 @@
+//! Annotated functions must not trigger lints in crates that deny them. This file is checked by
+//! `cargo clippy --all-targets -- -D warnings`.
+
+#![deny(clippy::all, clippy::pedantic)]
+
+use context_attribute::context;
+use failure::{ensure, Error};
+
+/// Square a number if it's less than 10.
+#[context]
+fn square(num: usize) -> Result<usize, Error> {
+    ensure!(num < 10, "Number was too large");
+    Ok(num * num)
+}
+
+/// Parse {input:?} as a number
+#[context]
+fn parse(input: &str) -> Result<usize, Error> {
+    Ok(input.parse()?)
+}
+
+struct Counter {
+    count: usize,
+}
+
+impl Counter {
+    /// Increment the counter past {self.count}
+    #[context]
+    fn increment(&mut self) -> Result<usize, Error> {
+        ensure!(self.count < 10, "Counter overflowed");
+        self.count += 1;
+        Ok(self.count)
+    }
+}
+
+/// Square a number in the background.
+#[context]
+async fn square_async(num: usize) -> Result<usize, Error> {
+    square(num)
+}
+
+#[test]
+fn lint_free() -> Result<(), Error> {
+    let mut counter = Counter { count: 0 };
+    counter.increment()?;
+    square(parse("2")?)?;
+    futures::executor::block_on(square_async(3))?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "context-stack")]
+
+use context_attribute::{context, context_stack};
+use failure::{bail, ensure, Error};
+use futures::executor::block_on;
+
+/// Load config for {name}
+#[context]
+fn load(name: &str) -> Result<Vec<String>, Error> {
+    parse(name.len())
+}
+
+/// Parse {len} bytes
+#[context]
+fn parse(len: usize) -> Result<Vec<String>, Error> {
+    if len == 0 {
+        bail!("empty config");
+    }
+    Ok(context_stack()
+        .into_iter()
+        .map(|msg| msg.into_owned())
+        .collect())
+}
+
+#[test]
+fn sync_stack() {
+    assert_eq!(
+        load("app").unwrap(),
+        ["Load config for app", "Parse 3 bytes"]
+    );
+    assert!(load("").is_err());
+    assert!(context_stack().is_empty());
+}
+
+/// Fetch the page
+#[context]
+async fn fetch() -> Result<Vec<String>, Error> {
+    futures::future::ready(()).await;
+    render().await
+}
+
+/// Render the page
+#[context]
+async fn render() -> Result<Vec<String>, Error> {
+    let before = context_stack();
+    futures::future::ready(()).await;
+    ensure!(
+        before == context_stack(),
+        "the stack changed across an await"
+    );
+    Ok(before.into_iter().map(|msg| msg.into_owned()).collect())
+}
+
+#[test]
+fn async_stack() {
+    let future = fetch();
+    assert!(context_stack().is_empty());
+    assert_eq!(
+        block_on(future).unwrap(),
+        ["Fetch the page", "Render the page"]
+    );
+    assert!(context_stack().is_empty());
+}
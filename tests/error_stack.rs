@@ -0,0 +1,52 @@
+#![cfg(feature = "error-stack")]
+
+use context_attribute::context;
+use error_stack::{Report, ResultExt};
+
+#[derive(Debug, thiserror::Error)]
+#[error("the store is unavailable")]
+struct StoreError;
+
+/// Fetch order {id}
+#[context]
+fn fetch(id: usize) -> Result<usize, Report<StoreError>> {
+    match id {
+        0 => Err(Report::new(StoreError)),
+        id => Ok(id * 2),
+    }
+}
+
+/// Read {path} from disk
+#[context(map(std::io::Error => "couldn't read {path}"))]
+fn read(path: &str) -> Result<String, Report<StoreError>> {
+    std::fs::read_to_string(path).change_context(StoreError)
+}
+
+fn printable(report: &Report<StoreError>) -> Vec<String> {
+    report
+        .frames()
+        .filter_map(|frame| match frame.kind() {
+            error_stack::FrameKind::Context(context) => Some(context.to_string()),
+            error_stack::FrameKind::Attachment(error_stack::AttachmentKind::Printable(
+                attachment,
+            )) => Some(attachment.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn error_stack_backend() {
+    assert_eq!(fetch(2).unwrap(), 4);
+    let report = fetch(0).unwrap_err();
+    assert_eq!(
+        printable(&report),
+        ["Fetch order 0", "the store is unavailable"]
+    );
+}
+
+#[test]
+fn error_stack_downcast() {
+    let report = read("does-not-exist.txt").unwrap_err();
+    assert_eq!(printable(&report)[0], "couldn't read does-not-exist.txt");
+}
@@ -0,0 +1,45 @@
+//! The exit code and output of a process whose `main` returns an error.
+//!
+//! The test runs itself again as a child process, which returns the error the way `main` would.
+
+use context_attribute::context;
+use std::process::{Command, ExitCode, Termination};
+
+/// The environment variable that tells the test it's running as the child process.
+const CHILD: &str = "CONTEXT_ATTRIBUTE_EXIT_CODE_CHILD";
+
+/// Load the config from {path}
+#[context]
+fn load(path: &str) -> Result<String, failure::Error> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+fn main_like() -> Result<(), failure::Error> {
+    println!("{}", load("does-not-exist.toml")?);
+    Ok(())
+}
+
+#[test]
+fn exits_with_context() {
+    if std::env::var_os(CHILD).is_some() {
+        // This is what the runtime does with the `ExitCode` that `main` reports.
+        match main_like().report() == ExitCode::SUCCESS {
+            true => std::process::exit(0),
+            false => std::process::exit(1),
+        }
+    }
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["exits_with_context", "--exact", "--nocapture"])
+        .env(CHILD, "1")
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Error: "), "{}", stderr);
+    assert!(
+        stderr.contains("Load the config from does-not-exist.toml"),
+        "{}",
+        stderr
+    );
+}
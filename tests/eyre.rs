@@ -0,0 +1,74 @@
+#![cfg(feature = "eyre")]
+
+use context_attribute::context;
+use eyre::{bail, Result};
+
+/// Check the number {num}
+#[context]
+fn check(num: usize) -> Result<usize> {
+    if num > 10 {
+        bail!("number was too large");
+    }
+    Ok(num)
+}
+
+/// Read {path} from disk
+#[context(note = "check that {path} exists")]
+fn read(path: &str) -> Result<String> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Load the {name} plugin
+///
+/// Plugins are looked up in the `plugins` directory.
+///
+/// Run `fetch-plugins` to download
+/// the default plugins.
+#[context(notes)]
+fn load(name: &str) -> Result<String> {
+    bail!("{} wasn't found", name)
+}
+
+// Reports capture the handler when they're created, so install it before creating any.
+fn install() {
+    let _ = color_eyre::install();
+}
+
+#[test]
+fn eyre_backend() {
+    install();
+    assert_eq!(check(2).unwrap(), 2);
+    let causes: Vec<String> = check(12)
+        .unwrap_err()
+        .chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Check the number 12", "number was too large"]);
+}
+
+#[test]
+fn eyre_note() {
+    install();
+    let err = read("does-not-exist.txt").unwrap_err();
+    assert_eq!(err.to_string(), "Read does-not-exist.txt from disk");
+    let report = format!("{:?}", err);
+    assert!(
+        report.contains("check that does-not-exist.txt exists"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn eyre_notes_from_paragraphs() {
+    install();
+    let err = load("audit").unwrap_err();
+    assert_eq!(err.to_string(), "Load the audit plugin");
+    let report = format!("{:?}", err);
+    for note in [
+        "Plugins are looked up in the `plugins` directory.",
+        "Run `fetch-plugins` to download the default plugins.",
+    ] {
+        assert!(report.contains(note), "{}", report);
+    }
+}
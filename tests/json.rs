@@ -0,0 +1,26 @@
+#![cfg(feature = "json")]
+
+use context_attribute::context;
+use failure::{bail, Error};
+
+/// Read {path} from disk
+#[context(json)]
+fn read(path: &str, retries: usize) -> Result<String, Error> {
+    if retries == 0 {
+        bail!("out of retries");
+    }
+    Ok(std::fs::read_to_string(path)?)
+}
+
+#[test]
+fn json_context() {
+    let err = read("address.txt", 0).unwrap_err();
+    let json: serde_json::Value = serde_json::from_str(&err.to_string()).unwrap();
+    assert_eq!(json["context"], "Read address.txt from disk");
+    assert_eq!(json["args"]["path"], r#""address.txt""#);
+    assert_eq!(json["args"]["retries"], "0");
+    assert!(json["location"]
+        .as_str()
+        .unwrap()
+        .starts_with("tests/json.rs:"));
+}
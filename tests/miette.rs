@@ -0,0 +1,39 @@
+#![cfg(feature = "miette")]
+
+use context_attribute::context;
+use miette::{bail, Diagnostic, Result};
+
+#[derive(Debug, thiserror::Error, Diagnostic)]
+#[error("the cache is locked")]
+struct Locked;
+
+/// Check the number {num}
+#[context]
+fn check(num: usize) -> Result<usize> {
+    if num > 10 {
+        bail!("number was too large");
+    }
+    Ok(num)
+}
+
+/// Clear the cache
+#[context(map(Locked => "the cache is in use"))]
+fn clear() -> Result<()> {
+    Err(Locked.into())
+}
+
+#[test]
+fn miette_backend() {
+    assert_eq!(check(2).unwrap(), 2);
+    let causes: Vec<String> = check(12)
+        .unwrap_err()
+        .chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Check the number 12", "number was too large"]);
+}
+
+#[test]
+fn miette_downcast() {
+    assert_eq!(clear().unwrap_err().to_string(), "the cache is in use");
+}
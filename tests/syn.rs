@@ -0,0 +1,53 @@
+#![cfg(feature = "syn")]
+
+use context_attribute::context;
+
+/// Parse the field name {name:?}
+#[context]
+fn field_name(name: &str) -> syn::Result<syn::Ident> {
+    syn::parse_str(name)
+}
+
+/// Parse the fields {names:?}
+#[context]
+fn field_names(names: &[&str]) -> Result<Vec<syn::Ident>, syn::Error> {
+    let mut idents = vec![];
+    let mut errors: Option<syn::Error> = None;
+    for name in names {
+        match syn::parse_str(name) {
+            Ok(ident) => idents.push(ident),
+            Err(err) => match &mut errors {
+                Some(errors) => errors.combine(err),
+                None => errors = Some(err),
+            },
+        }
+    }
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(idents),
+    }
+}
+
+#[test]
+fn syn_errors() {
+    assert_eq!(field_name("port").unwrap(), "port");
+    let err = field_name("2port").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Parse the field name \"2port\": expected identifier"
+    );
+}
+
+#[test]
+fn combined_syn_errors() {
+    assert_eq!(field_names(&["host", "port"]).unwrap().len(), 2);
+    let err = field_names(&["1host", "port", "2port"]).unwrap_err();
+    let messages: Vec<String> = err.into_iter().map(|err| err.to_string()).collect();
+    assert_eq!(
+        messages,
+        [
+            "Parse the fields [\"1host\", \"port\", \"2port\"]: expected identifier",
+            "Parse the fields [\"1host\", \"port\", \"2port\"]: expected identifier"
+        ]
+    );
+}
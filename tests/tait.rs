@@ -0,0 +1,30 @@
+//! Return types that are type-alias `impl Trait`s. These need a nightly compiler, so the tests
+//! only run with `RUSTFLAGS="--cfg nightly" cargo +nightly test --test tait`.
+
+#![cfg(nightly)]
+#![feature(type_alias_impl_trait)]
+
+use context_attribute::context;
+use failure::{ensure, Error};
+use std::fmt::{Debug, Display};
+
+type Label = Result<impl Display + Debug, Error>;
+
+/// Build the label for item {id}
+#[context]
+#[define_opaque(Label)]
+fn label(id: usize) -> Label {
+    ensure!(id > 0, "ids start at 1");
+    Ok(format!("item #{}", id))
+}
+
+#[test]
+fn type_alias_impl_trait() {
+    assert_eq!(label(3).unwrap().to_string(), "item #3");
+    let causes: Vec<String> = label(0)
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Build the label for item 0", "ids start at 1"]);
+}
@@ -0,0 +1,39 @@
+//! The output `main` prints when it returns an error, through `Termination`.
+//!
+//! Backtraces are disabled for the whole test binary, so this file has a single test.
+
+use context_attribute::context;
+
+/// Square a number if it's less than 10.
+#[context]
+fn square(num: usize) -> Result<usize, failure::Error> {
+    failure::ensure!(num < 10, "Number was too large");
+    Ok(num * num)
+}
+
+#[cfg(feature = "anyhow")]
+/// Square a number if it's less than 10.
+#[context]
+fn square_anyhow(num: usize) -> anyhow::Result<usize> {
+    anyhow::ensure!(num < 10, "Number was too large");
+    Ok(num * num)
+}
+
+#[test]
+fn main_error_output() {
+    std::env::set_var("RUST_BACKTRACE", "0");
+    std::env::set_var("RUST_LIB_BACKTRACE", "0");
+
+    // `main` prints errors as `Error: {:?}`.
+    let output = format!("Error: {:?}", square(12).unwrap_err());
+    assert_eq!(output, "Error: ErrorMessage { msg: \"Number was too large\" }\n\nSquare a number if it's less than 10.");
+
+    #[cfg(feature = "anyhow")]
+    {
+        let output = format!("Error: {:?}", square_anyhow(12).unwrap_err());
+        assert_eq!(
+            output,
+            "Error: Square a number if it's less than 10.\n\nCaused by:\n    Number was too large"
+        );
+    }
+}
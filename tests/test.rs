@@ -1,9 +1,2444 @@
 extern crate context_attribute;
 extern crate failure;
 
-use failure::Error;
+use context_attribute::context;
+use failure::{bail, Error};
+use futures::executor::block_on;
+use futures::future::{BoxFuture, FutureExt};
 
 #[test]
 fn should_work() -> Result<(), Error> {
     Ok(())
 }
+
+fn some_future(fail: bool) -> BoxFuture<'static, Result<usize, Error>> {
+    async move {
+        if fail {
+            bail!("future failed");
+        }
+        Ok(2)
+    }
+    .boxed()
+}
+
+/// Double the output of a future
+#[context]
+async fn double_future(fail: bool) -> Result<usize, Error> {
+    let x = some_future(fail).await?;
+    Ok(x * 2)
+}
+
+#[test]
+fn async_await_question_mark() {
+    assert_eq!(block_on(double_future(false)).unwrap(), 4);
+
+    let err = block_on(double_future(true)).unwrap_err();
+    assert_eq!(err.to_string(), "Double the output of a future");
+    let causes: Vec<String> = err.iter_chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(causes, ["Double the output of a future", "future failed"]);
+}
+
+#[cfg(not(feature = "strict"))]
+#[allow(clippy::empty_docs)]
+///
+#[context]
+fn empty_doc() -> Result<(), Error> {
+    bail!("empty doc failed");
+}
+
+#[test]
+#[cfg(not(feature = "strict"))]
+fn empty_doc_uses_fn_name() {
+    let err = empty_doc().unwrap_err();
+    assert_eq!(err.to_string(), "empty_doc");
+}
+
+#[cfg(all(feature = "strict", not(feature = "lint-infallible")))]
+#[context]
+fn undocumented_square(num: usize) -> Result<usize, Error> {
+    Ok(num * num)
+}
+
+#[test]
+#[cfg(all(feature = "strict", not(feature = "lint-infallible")))]
+fn strict_allows_undocumented_infallible_fns() {
+    assert_eq!(undocumented_square(3).unwrap(), 9);
+}
+
+struct Job {
+    id: usize,
+}
+
+impl Job {
+    /// failed processing job {self.id}
+    #[context]
+    fn process(&self, fail: bool) -> Result<usize, Error> {
+        if fail {
+            bail!("job failed");
+        }
+        Ok(self.id)
+    }
+}
+
+#[test]
+fn interpolate_self_fields() {
+    let job = Job { id: 12 };
+    assert_eq!(job.process(false).unwrap(), 12);
+    let err = job.process(true).unwrap_err();
+    assert_eq!(err.to_string(), "failed processing job 12");
+}
+
+/// Read {path} with {{braces}}
+#[context]
+fn read(path: &str) -> Result<String, Error> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+#[test]
+fn interpolate_args() {
+    let err = read("does-not-exist.txt").unwrap_err();
+    assert_eq!(err.to_string(), "Read does-not-exist.txt with {braces}");
+}
+
+#[async_trait::async_trait]
+trait Fetch {
+    async fn fetch(&self, fail: bool) -> Result<usize, Error>;
+}
+
+struct Fetcher {
+    name: &'static str,
+}
+
+#[async_trait::async_trait]
+impl Fetch for Fetcher {
+    /// Fetch from {self.name}
+    #[context]
+    async fn fetch(&self, fail: bool) -> Result<usize, Error> {
+        let x = some_future(fail).await?;
+        Ok(x + 1)
+    }
+}
+
+#[test]
+fn async_trait_method() {
+    let fetcher = Fetcher { name: "upstream" };
+    assert_eq!(block_on(fetcher.fetch(false)).unwrap(), 3);
+
+    let err = block_on(fetcher.fetch(true)).unwrap_err();
+    let causes: Vec<String> = err.iter_chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(causes, ["Fetch from upstream", "future failed"]);
+}
+
+/// Sum {num} with itself
+#[context]
+fn nested_blocks(num: usize) -> Result<usize, Error> {
+    {
+        let num = num * 2;
+        {
+            let num: usize = num.to_string().parse()?;
+            if num > 10 {
+                bail!("{} is too large", num);
+            }
+            Ok(num)
+        }
+    }
+}
+
+#[test]
+fn block_body() {
+    assert_eq!(nested_blocks(2).unwrap(), 4);
+
+    let err = nested_blocks(6).unwrap_err();
+    let causes: Vec<String> = err.iter_chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(causes, ["Sum 6 with itself", "12 is too large"]);
+}
+
+/// Read address.txt
+#[context(id)]
+fn with_id() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+/// Read address.txt
+#[context(id)]
+fn with_other_id() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+#[test]
+fn error_id() {
+    let msg = with_id().unwrap_err().to_string();
+    let (id, rest) = msg.split_at(9);
+    assert!(id.starts_with("[E#") && id.ends_with("] "), "{}", msg);
+    assert!(id[3..7].chars().all(|c| c.is_ascii_hexdigit()), "{}", msg);
+    assert_eq!(rest, "Read address.txt");
+
+    assert_eq!(with_id().unwrap_err().to_string(), msg);
+    assert_ne!(with_other_id().unwrap_err().to_string(), msg);
+}
+
+struct Users;
+struct Orders;
+
+#[context(id)]
+impl Users {
+    /// Load it
+    fn load(&self) -> Result<(), Error> {
+        bail!("no such table");
+    }
+}
+
+#[context(id)]
+impl Orders {
+    /// Load it
+    fn load(&self) -> Result<(), Error> {
+        bail!("no such table");
+    }
+}
+
+#[test]
+fn error_id_of_methods() {
+    let users = Users.load().unwrap_err().to_string();
+    let orders = Orders.load().unwrap_err().to_string();
+    assert!(
+        users.starts_with("[E#") && users.ends_with("] Load it"),
+        "{}",
+        users
+    );
+    assert!(
+        orders.starts_with("[E#") && orders.ends_with("] Load it"),
+        "{}",
+        orders
+    );
+    assert_ne!(users, orders);
+    assert_eq!(Users.load().unwrap_err().to_string(), users);
+}
+
+#[test]
+fn nested_functions() {
+    /// Read the outer file
+    #[context]
+    fn outer(fail: bool) -> Result<usize, Error> {
+        /// Read the inner file
+        #[context]
+        fn inner(fail: bool) -> Result<usize, Error> {
+            if fail {
+                bail!("no such file");
+            }
+            Ok(1)
+        }
+
+        let result = inner(fail)?;
+        Ok(result + 1)
+    }
+
+    assert_eq!(outer(false).unwrap(), 2);
+    let err = outer(true).unwrap_err();
+    let causes: Vec<String> = err.iter_chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(
+        causes,
+        ["Read the outer file", "Read the inner file", "no such file"]
+    );
+}
+
+fn tr(key: &str) -> String {
+    match key {
+        "error.read_config" => "Konfiguration konnte nicht gelesen werden".to_string(),
+        key => key.to_string(),
+    }
+}
+
+mod i18n {
+    pub fn tr(key: &str) -> &'static str {
+        match key {
+            "error.read_config" => "No se pudo leer la configuración",
+            _ => "unknown",
+        }
+    }
+}
+
+#[context(t = "error.read_config")]
+fn read_config() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+/// The doc comment isn't used when translating.
+#[context(t = "error.read_config", t_fn = i18n::tr)]
+fn read_config_es() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+#[test]
+fn translated_context() {
+    let err = read_config().unwrap_err();
+    assert_eq!(err.to_string(), "Konfiguration konnte nicht gelesen werden");
+    let err = read_config_es().unwrap_err();
+    assert_eq!(err.to_string(), "No se pudo leer la configuración");
+}
+
+thread_local! {
+    static AUDIT_LOG: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(vec![]) };
+}
+
+fn log_success(msg: &str) {
+    AUDIT_LOG.with(|log| log.borrow_mut().push(msg.to_string()));
+}
+
+/// Transfer {amount} credits
+#[context(on_ok = log_success)]
+fn transfer(amount: usize) -> Result<usize, Error> {
+    if amount > 100 {
+        bail!("insufficient funds");
+    }
+    Ok(amount)
+}
+
+#[test]
+fn on_ok_side_effect() {
+    assert_eq!(transfer(10).unwrap(), 10);
+    assert_eq!(
+        transfer(200).unwrap_err().to_string(),
+        "Transfer 200 credits"
+    );
+    AUDIT_LOG.with(|log| assert_eq!(*log.borrow(), ["Transfer 10 credits"]));
+}
+
+macro_rules! parse_number {
+    ($input:expr) => {
+        $input.parse::<usize>().map_err(Error::from)
+    };
+}
+
+/// Parse {input} as a number
+#[context]
+fn macro_tail(input: &str) -> Result<usize, Error> {
+    parse_number!(input)
+}
+
+/// Refuse {input}
+#[context]
+fn bail_tail(input: &str) -> Result<usize, Error> {
+    let _ = input;
+    bail!("refused")
+}
+
+/// Not done yet
+#[context]
+fn todo_tail() -> Result<usize, Error> {
+    todo!()
+}
+
+#[test]
+fn macro_tails() {
+    assert_eq!(macro_tail("12").unwrap(), 12);
+    let causes: Vec<String> = macro_tail("x")
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        ["Parse x as a number", "invalid digit found in string"]
+    );
+
+    let causes: Vec<String> = bail_tail("x")
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Refuse x", "refused"]);
+}
+
+#[test]
+#[should_panic(expected = "not yet implemented")]
+fn todo_tail_panics() {
+    let _ = todo_tail();
+}
+
+/// Load {input}
+#[context(map(std::io::Error => "disk problem", std::num::ParseIntError => "bad number {input}"))]
+fn load(input: &str) -> Result<usize, Error> {
+    match input {
+        "missing" => Ok(std::fs::read_to_string("does-not-exist.txt")?.len()),
+        "nested" => Ok(macro_tail("x")?),
+        "other" => bail!("something else"),
+        input => Ok(input.parse()?),
+    }
+}
+
+#[test]
+fn map_error_types() {
+    assert_eq!(load("12").unwrap(), 12);
+    assert_eq!(load("missing").unwrap_err().to_string(), "disk problem");
+    assert_eq!(load("x").unwrap_err().to_string(), "bad number x");
+    assert_eq!(load("nested").unwrap_err().to_string(), "bad number nested");
+    assert_eq!(load("other").unwrap_err().to_string(), "Load other");
+}
+
+trait Service {
+    type Output;
+    type Error;
+
+    fn call(&self, input: usize) -> Result<Self::Output, Self::Error>;
+}
+
+struct Doubler;
+
+impl Service for Doubler {
+    type Output = usize;
+    type Error = Error;
+
+    fn call(&self, input: usize) -> Result<usize, Error> {
+        if input > 10 {
+            bail!("input too large");
+        }
+        Ok(input * 2)
+    }
+}
+
+/// Run the service with {input}
+#[context]
+fn run<S>(service: S, input: usize) -> Result<S::Output, S::Error>
+where
+    S: Service<Error = Error>,
+{
+    service.call(input)
+}
+
+#[test]
+fn associated_error_type() {
+    assert_eq!(run(Doubler, 2).unwrap(), 4);
+    let causes: Vec<String> = run(Doubler, 12)
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Run the service with 12", "input too large"]);
+}
+
+///   Indented context
+#[context]
+fn indented() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+///   Indented context
+#[context(no_trim)]
+fn indented_no_trim() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+#[test]
+fn trim_leading_space() {
+    assert_eq!(indented().unwrap_err().to_string(), "  Indented context");
+    assert_eq!(
+        indented_no_trim().unwrap_err().to_string(),
+        "   Indented context"
+    );
+}
+
+#[derive(Debug, Default)]
+struct ServerBuilder {
+    host: &'static str,
+    port: u16,
+}
+
+impl ServerBuilder {
+    /// Set the port to {p}
+    #[context]
+    fn with_port(self, p: u16) -> Result<Self, Error> {
+        if p < 1024 {
+            bail!("port is reserved");
+        }
+        Ok(Self { port: p, ..self })
+    }
+}
+
+#[test]
+fn builder_returning_self() {
+    let builder = ServerBuilder::default().with_port(8080).unwrap();
+    assert_eq!((builder.host, builder.port), ("", 8080));
+    let causes: Vec<String> = ServerBuilder::default()
+        .with_port(80)
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Set the port to 80", "port is reserved"]);
+}
+
+/// Run the plugin
+#[no_mangle]
+#[context]
+pub fn context_plugin_entry(fail: bool) -> Result<usize, Error> {
+    if fail {
+        bail!("plugin failed");
+    }
+    Ok(1)
+}
+
+// Linking against the unmangled name fails if `#[no_mangle]` ended up on more than one symbol.
+extern "Rust" {
+    #[link_name = "context_plugin_entry"]
+    fn plugin_entry(fail: bool) -> Result<usize, Error>;
+}
+
+#[test]
+fn no_mangle() {
+    assert_eq!(unsafe { plugin_entry(false) }.unwrap(), 1);
+    assert_eq!(
+        unsafe { plugin_entry(true) }.unwrap_err().to_string(),
+        "Run the plugin"
+    );
+}
+
+/// Read the config,
+/// then parse it
+#[context]
+fn multi_line() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+/// Couldn't start the server.
+///
+/// Check that port {port} is free.
+#[context(join = "\n")]
+fn multi_paragraph(port: u16) -> Result<(), Error> {
+    let _ = port;
+    bail!("address in use");
+}
+
+#[test]
+fn join_lines() {
+    assert_eq!(
+        multi_line().unwrap_err().to_string(),
+        "Read the config, then parse it"
+    );
+    let err = multi_paragraph(80).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Couldn't start the server.\n\nCheck that port 80 is free."
+    );
+}
+
+/// Fetch the profile of user {id}
+#[context]
+async fn fetch_profile(id: u32) -> Result<String, Error> {
+    let task = tokio::spawn(async move {
+        if id == 0 {
+            bail!("no such user");
+        }
+        Ok(format!("user {}", id))
+    });
+    let profile = task.await??;
+    Ok(profile)
+}
+
+#[tokio::test]
+async fn spawned_task() {
+    assert_eq!(fetch_profile(1).await.unwrap(), "user 1");
+    let causes: Vec<String> = fetch_profile(0)
+        .await
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Fetch the profile of user 0", "no such user"]);
+}
+
+type Fallible<T> = Result<T, Error>;
+
+/// Double {num}
+#[context]
+fn double_alias(num: usize) -> Fallible<usize> {
+    if num > 10 {
+        bail!("number was too large");
+    }
+    Ok(num * 2)
+}
+
+/// Find the user called {name}
+#[context]
+fn find_user(name: &str) -> Option<usize> {
+    ["alice", "bob"].iter().position(|user| *user == name)
+}
+
+/// Find the admin called {name}
+#[context(on_ok = log_success)]
+fn find_admin(name: &str) -> Option<usize> {
+    ["alice"].iter().position(|user| *user == name)
+}
+
+#[test]
+fn contextable_returns() {
+    assert_eq!(double_alias(2).unwrap(), 4);
+    assert_eq!(double_alias(12).unwrap_err().to_string(), "Double 12");
+    assert_eq!(find_user("bob"), Some(1));
+    assert_eq!(find_user("carol"), None);
+    // A `None` isn't an error, so it's logged as a success.
+    assert_eq!(find_admin("alice"), Some(0));
+    assert_eq!(find_admin("bob"), None);
+    AUDIT_LOG.with(|log| {
+        assert_eq!(
+            *log.borrow(),
+            ["Find the admin called alice", "Find the admin called bob"]
+        )
+    });
+}
+
+fn assert_send<T: Send + 'static>(value: T) -> T {
+    value
+}
+
+fn assert_send_sync<T: Send + Sync + 'static>(value: T) -> T {
+    value
+}
+
+#[tokio::test]
+async fn send_across_threads() {
+    let future = assert_send(double_future(true));
+    let err = tokio::spawn(future).await.unwrap().unwrap_err();
+    let err = std::thread::spawn(move || assert_send_sync(err).to_string())
+        .join()
+        .unwrap();
+    assert_eq!(err, "Double the output of a future");
+
+    let err = assert_send(tokio::spawn(fetch_profile(0)))
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Fetch the profile of user 0");
+}
+
+/// Expected a body like {"port": 80}
+#[context(literal)]
+fn parse_body(body: &str) -> Result<u16, Error> {
+    Ok(body.parse()?)
+}
+
+#[test]
+fn literal_doc() {
+    assert_eq!(
+        parse_body("{}").unwrap_err().to_string(),
+        r#"Expected a body like {"port": 80}"#
+    );
+}
+
+/// Connect to {addr}
+#[context(eprintln)]
+fn connect(addr: &str) -> Result<(), Error> {
+    bail!("connection refused by {}", addr);
+}
+
+/// Refresh the {name} cache
+#[context(eprintln, level = "warn")]
+fn refresh(name: &str) -> Result<(), Error> {
+    bail!("{} is already fresh", name);
+}
+
+#[test]
+fn eprintln_still_returns_error() {
+    assert_eq!(
+        refresh("avatar").unwrap_err().to_string(),
+        "Refresh the avatar cache"
+    );
+    let causes: Vec<String> = connect("db:5432")
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        ["Connect to db:5432", "connection refused by db:5432"]
+    );
+}
+
+#[context(default = "operation failed for {name}")]
+fn undocumented(name: &str) -> Result<(), Error> {
+    bail!("{} not found", name);
+}
+
+/// Look up {name}
+#[context(default = "operation failed")]
+fn documented(name: &str) -> Result<(), Error> {
+    bail!("{} not found", name);
+}
+
+#[test]
+fn default_message() {
+    assert_eq!(
+        undocumented("alice").unwrap_err().to_string(),
+        "operation failed for alice"
+    );
+    assert_eq!(
+        documented("alice").unwrap_err().to_string(),
+        "Look up alice"
+    );
+}
+
+/// Sync the inventory
+#[context(msg = concat!("Sync ", env!("CARGO_PKG_NAME")))]
+fn sync_inventory() -> Result<(), Error> {
+    bail!("store offline");
+}
+
+#[context(msg = "Restock {item}", prefix = "store")]
+fn restock(item: &str) -> Result<(), Error> {
+    bail!("{} is discontinued", item);
+}
+
+#[test]
+fn expression_message() {
+    assert_eq!(
+        sync_inventory().unwrap_err().to_string(),
+        "Sync context-attribute"
+    );
+    assert_eq!(
+        restock("widgets").unwrap_err().to_string(),
+        "store: Restock widgets"
+    );
+}
+
+/// Fetch item {id}
+#[context]
+fn fetch_later(id: usize) -> impl std::future::Future<Output = Result<usize, Error>> {
+    async move {
+        let item = some_future(id == 0).await?;
+        Ok(item + id)
+    }
+}
+
+#[test]
+fn impl_future() {
+    assert_eq!(block_on(fetch_later(1)).unwrap(), 3);
+    let causes: Vec<String> = block_on(fetch_later(0))
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Fetch item 0", "future failed"]);
+}
+
+/// Label {n}
+#[context]
+fn label(n: u8) -> Result<impl std::fmt::Display, Error> {
+    if n == 0 {
+        bail!("no label for zero");
+    }
+    Ok(n * 2)
+}
+
+/// Label {n} later
+#[context]
+async fn label_later(n: u8) -> Result<impl std::fmt::Display, Error> {
+    Ok(label(n)?.to_string())
+}
+
+/// Fetch labels up to {n}
+#[context]
+fn fetch_labels(
+    n: u8,
+) -> impl std::future::Future<Output = Result<impl Iterator<Item = u8>, Error>> {
+    async move {
+        label(n)?;
+        Ok(1..=n)
+    }
+}
+
+#[test]
+fn impl_trait_results() {
+    assert_eq!(label(2).unwrap().to_string(), "4");
+    assert_eq!(label(0).err().unwrap().to_string(), "Label 0");
+    assert_eq!(block_on(label_later(3)).unwrap().to_string(), "6");
+    assert_eq!(
+        block_on(label_later(0)).err().unwrap().to_string(),
+        "Label 0 later"
+    );
+    assert_eq!(block_on(fetch_labels(3)).unwrap().sum::<u8>(), 6);
+    let err = block_on(fetch_labels(0)).err().unwrap();
+    assert_eq!(err.to_string(), "Fetch labels up to 0");
+}
+
+/// Handle request {id}
+#[context(thread)]
+fn handle(id: usize) -> Result<(), Error> {
+    bail!("request {} timed out", id);
+}
+
+#[test]
+fn thread_name() {
+    let worker = std::thread::Builder::new().name("worker-1".into());
+    let msg = worker
+        .spawn(|| handle(3).unwrap_err().to_string())
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(msg, "Handle request 3 (on thread worker-1)");
+
+    let msg = std::thread::spawn(|| handle(4).unwrap_err().to_string())
+        .join()
+        .unwrap();
+    assert!(
+        msg.starts_with("Handle request 4 (on thread ThreadId("),
+        "{}",
+        msg
+    );
+}
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Pass,
+    Fail(String),
+}
+
+impl context_attribute::WithContext for Verdict {
+    fn with_context<F>(self, f: F) -> Self
+    where
+        F: FnOnce() -> std::borrow::Cow<'static, str>,
+    {
+        match self {
+            Verdict::Fail(reason) => Verdict::Fail(format!("{}: {}", f(), reason)),
+            pass => pass,
+        }
+    }
+}
+
+/// Check {name}
+#[context(on_ok = log_success)]
+fn check(name: &str) -> Verdict {
+    match name {
+        "ok" => Verdict::Pass,
+        name => Verdict::Fail(format!("{} is broken", name)),
+    }
+}
+
+#[test]
+fn custom_result_type() {
+    assert_eq!(check("ok"), Verdict::Pass);
+    assert_eq!(
+        check("disk"),
+        Verdict::Fail("Check disk: disk is broken".into())
+    );
+    AUDIT_LOG.with(|log| assert_eq!(*log.borrow(), ["Check ok"]));
+}
+
+/// Write {key} to disk
+#[context(id, prefix = "storage")]
+fn store(key: &str) -> Result<(), Error> {
+    bail!("disk full while writing {}", key);
+}
+
+#[test]
+fn category_prefix() {
+    let msg = store("user:1").unwrap_err().to_string();
+    assert!(msg.starts_with("[E#"), "{}", msg);
+    assert_eq!(&msg[9..], "storage: Write user:1 to disk");
+}
+
+struct Labeled<T> {
+    value: T,
+}
+
+impl<T> Labeled<T> {
+    /// Describe the value
+    #[context]
+    fn describe(&self) -> Result<String, Error>
+    where
+        T: std::fmt::Display,
+    {
+        let text = self.value.to_string();
+        if text.is_empty() {
+            bail!("nothing to describe");
+        }
+        Ok(text)
+    }
+}
+
+/// Parse the digits
+#[context]
+fn parse_digits(input: &str) -> Result<u64, Error>
+where
+    u64: std::str::FromStr<Err = std::num::ParseIntError>,
+{
+    Ok(input.parse()?)
+}
+
+#[test]
+fn where_clause_without_generics() {
+    assert_eq!(Labeled { value: 3 }.describe().unwrap(), "3");
+    assert_eq!(
+        Labeled { value: "" }.describe().unwrap_err().to_string(),
+        "Describe the value"
+    );
+    assert_eq!(parse_digits("12").unwrap(), 12);
+    assert_eq!(
+        parse_digits("x").unwrap_err().to_string(),
+        "Parse the digits"
+    );
+}
+
+#[test]
+fn context_async_block() {
+    let future = context_attribute::context_async!("Fetch the page", async {
+        let page = some_future(false).await?;
+        Ok::<_, Error>(page * 2)
+    });
+    assert_eq!(block_on(future).unwrap(), 4);
+
+    let future = context_attribute::context_async!("Fetch the page", some_future(true));
+    let causes: Vec<String> = block_on(future)
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Fetch the page", "future failed"]);
+}
+
+type Command = Box<dyn Fn(usize, &str) -> Result<usize, Error>>;
+
+#[test]
+fn context_closure() {
+    let parse =
+        context_attribute::context_closure!(
+            "Parse the number",
+            |input: &str| -> Result<usize, Error> { Ok(input.parse()?) }
+        );
+    assert_eq!(parse("12").unwrap(), 12);
+    assert_eq!(parse("x").unwrap_err().to_string(), "Parse the number");
+    assert_eq!(parse("y").unwrap_err().to_string(), "Parse the number");
+
+    let base = 10;
+    let add = context_attribute::context_closure!(format!("Add to {}", base), move |a: usize,
+                                                                                    b: &str|
+          -> Result<
+        usize,
+        Error,
+    > {
+        Ok(base + a + b.parse::<usize>()?)
+    });
+    let add: Command = Box::new(add);
+    assert_eq!(add(1, "2").unwrap(), 13);
+    assert_eq!(add(1, "x").unwrap_err().to_string(), "Add to 10");
+
+    let mut seen = 0;
+    let mut count =
+        context_attribute::context_closure!("Count the item", |x: u32| -> Result<u32, Error> {
+            seen += 1;
+            match x {
+                0 => bail!("empty item"),
+                x => Ok(x),
+            }
+        });
+    assert_eq!(count(3).unwrap(), 3);
+    assert_eq!(count(0).unwrap_err().to_string(), "Count the item");
+    assert_eq!(seen, 2);
+
+    let nothing = context_attribute::context_closure!("Do nothing", || -> Result<(), Error> {
+        bail!("nothing to do")
+    });
+    let causes: Vec<String> = nothing()
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Do nothing", "nothing to do"]);
+}
+
+/// Read the v1.2 config. The config lives in the data directory, and is
+/// created on first run.
+#[context(summary)]
+fn read_config_summary() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+/// Read the config
+#[context(summary)]
+fn read_config_one_sentence() -> Result<(), Error> {
+    bail!("no such file");
+}
+
+#[test]
+fn summary_sentence() {
+    assert_eq!(
+        read_config_summary().unwrap_err().to_string(),
+        "Read the v1.2 config."
+    );
+    assert_eq!(
+        read_config_one_sentence().unwrap_err().to_string(),
+        "Read the config"
+    );
+}
+
+/// Validate the username {name:?}
+#[context]
+fn validate(name: &str) -> Result<(), Error> {
+    if name.is_empty() {
+        return Err(failure::err_msg("empty"));
+    }
+    if name.len() > 8 {
+        return Err(failure::err_msg("too long"));
+    }
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            return Err(failure::format_err!("invalid character {:?}", c));
+        }
+    }
+    Ok(())
+}
+
+/// Validate the username {name:?} later
+#[context]
+async fn validate_later(name: &str) -> Result<(), Error> {
+    if name.is_empty() {
+        return Err(failure::err_msg("empty"));
+    }
+    match name.len() {
+        0..=8 => Ok(()),
+        _ => return Err(failure::err_msg("too long")),
+    }
+}
+
+#[test]
+fn early_returns() {
+    let causes = |err: Error| {
+        err.iter_chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert!(validate("alice").is_ok());
+    assert_eq!(
+        causes(validate("").unwrap_err()),
+        [r#"Validate the username """#, "empty"]
+    );
+    assert_eq!(
+        causes(validate("alexander1").unwrap_err()),
+        [r#"Validate the username "alexander1""#, "too long"]
+    );
+    assert_eq!(
+        causes(validate("a-b").unwrap_err()),
+        [r#"Validate the username "a-b""#, "invalid character '-'"]
+    );
+
+    assert!(block_on(validate_later("alice")).is_ok());
+    assert_eq!(
+        causes(block_on(validate_later("")).unwrap_err()),
+        [r#"Validate the username "" later"#, "empty"]
+    );
+    let err = block_on(validate_later("alexander1")).unwrap_err();
+    assert_eq!(
+        causes(err),
+        [r#"Validate the username "alexander1" later"#, "too long"]
+    );
+}
+
+#[context]
+trait Storage {
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Load the value of {key}
+    fn load(&self, key: &str) -> Result<usize, Error> {
+        match self.get(key) {
+            Some(value) => Ok(value.parse()?),
+            None => bail!("no such key"),
+        }
+    }
+
+    /// Count the keys
+    #[context(skip)]
+    fn count(&self) -> usize {
+        0
+    }
+}
+
+struct MemoryStorage;
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "answer" => Some("42".to_string()),
+            "name" => Some("alice".to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn trait_default_methods() {
+    let storage = MemoryStorage;
+    assert_eq!(storage.load("answer").unwrap(), 42);
+    let causes: Vec<String> = storage
+        .load("name")
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        ["Load the value of name", "invalid digit found in string"]
+    );
+    assert_eq!(
+        storage.load("missing").unwrap_err().to_string(),
+        "Load the value of missing"
+    );
+    assert_eq!(storage.count(), 0);
+}
+
+/// Advance the parser from {state}
+#[context]
+fn advance(state: usize) -> std::ops::ControlFlow<Error, usize> {
+    match state {
+        3 => std::ops::ControlFlow::Break(failure::err_msg("unexpected end of input")),
+        _ => std::ops::ControlFlow::Continue(state + 1),
+    }
+}
+
+#[test]
+fn control_flow() {
+    assert!(matches!(advance(1), std::ops::ControlFlow::Continue(2)));
+    let err = match advance(3) {
+        std::ops::ControlFlow::Break(err) => err,
+        std::ops::ControlFlow::Continue(state) => panic!("continued to {}", state),
+    };
+    let causes: Vec<String> = err.iter_chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(
+        causes,
+        ["Advance the parser from 3", "unexpected end of input"]
+    );
+}
+
+/// Fetch the index
+#[context(attempt = attempt)]
+fn fetch_index(attempt: usize) -> Result<usize, Error> {
+    match attempt {
+        3 => Ok(42),
+        _ => bail!("connection reset"),
+    }
+}
+
+#[test]
+fn attempt() {
+    let mut errors = vec![];
+    for attempt in 1.. {
+        match fetch_index(attempt) {
+            Ok(index) => {
+                assert_eq!(index, 42);
+                break;
+            }
+            Err(err) => errors.push(err.to_string()),
+        }
+    }
+    assert_eq!(
+        errors,
+        ["Fetch the index (attempt 1)", "Fetch the index (attempt 2)"]
+    );
+}
+
+#[context]
+trait Notifier {
+    fn send(&self, to: &str) -> Result<(), Error>;
+
+    /// Notify the {count} subscribers
+    fn broadcast(&self, count: usize) -> Result<(), Error> {
+        for _ in 0..count {
+            self.send("subscriber")?;
+        }
+        Ok(())
+    }
+}
+
+struct Offline;
+
+impl Notifier for Offline {
+    /// Send a notification to {to}
+    #[context]
+    fn send(&self, to: &str) -> Result<(), Error> {
+        bail!("{} is offline", to);
+    }
+}
+
+#[test]
+fn trait_objects() {
+    let notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(Offline)];
+    let causes: Vec<String> = notifiers[0]
+        .broadcast(2)
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        [
+            "Notify the 2 subscribers",
+            "Send a notification to subscriber",
+            "subscriber is offline"
+        ]
+    );
+}
+
+/// Warm the cache
+#[context(debug_only)]
+fn warm_cache() -> Result<(), Error> {
+    bail!("cache offline");
+}
+
+#[test]
+fn debug_only() {
+    let expected = match cfg!(debug_assertions) {
+        true => "Warm the cache",
+        false => "cache offline",
+    };
+    assert_eq!(warm_cache().unwrap_err().to_string(), expected);
+}
+
+/// Find the first word of {text:?}
+#[context]
+fn first_word<'a>(text: &'a str) -> Result<&'a str, Error> {
+    match text.split_whitespace().next() {
+        Some(word) => Ok(word),
+        None => bail!("no words"),
+    }
+}
+
+/// Find the first free slot
+#[context]
+fn first_free(slots: &mut [Option<usize>]) -> Result<&mut Option<usize>, Error> {
+    match slots.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => Ok(slot),
+        None => bail!("all slots are taken"),
+    }
+}
+
+#[test]
+fn borrowed_returns() {
+    assert_eq!(first_word("hello world").unwrap(), "hello");
+    assert_eq!(
+        first_word(" ").unwrap_err().to_string(),
+        "Find the first word of \" \""
+    );
+
+    let mut slots = [Some(1), None];
+    *first_free(&mut slots).unwrap() = Some(2);
+    assert_eq!(slots, [Some(1), Some(2)]);
+    assert_eq!(
+        first_free(&mut slots).unwrap_err().to_string(),
+        "Find the first free slot"
+    );
+}
+
+#[context(optional_doc)]
+trait Cache {
+    /// Read {key} from the cache
+    fn read(&self, key: &str) -> Result<String, Error> {
+        bail!("{} is not cached", key);
+    }
+}
+
+// Strict mode requires a doc comment on fallible functions, even with `optional_doc`.
+#[cfg(not(feature = "strict"))]
+#[context(optional_doc)]
+trait Evict {
+    fn evict(&self, key: &str) -> Result<(), Error> {
+        bail!("{} is pinned", key);
+    }
+}
+
+struct NoCache;
+
+impl Cache for NoCache {}
+
+#[cfg(not(feature = "strict"))]
+impl Evict for NoCache {}
+
+#[test]
+fn optional_doc() {
+    assert_eq!(
+        NoCache.read("users").unwrap_err().to_string(),
+        "Read users from the cache"
+    );
+    #[cfg(not(feature = "strict"))]
+    assert_eq!(
+        NoCache.evict("users").unwrap_err().to_string(),
+        "users is pinned"
+    );
+}
+
+/// Parse {input:?} as a setting
+#[context]
+fn parse_setting<T>(input: &str) -> Result<T, Error>
+where
+    T: std::str::FromStr,
+    T::Err: failure::Fail,
+{
+    Ok(T::from_str(input.trim())?)
+}
+
+#[test]
+fn generic_associated_functions() {
+    assert_eq!(parse_setting::<u16>(" 8080 ").unwrap(), 8080);
+    assert!(parse_setting::<bool>("true").unwrap());
+    let causes: Vec<String> = parse_setting::<u16>("eighty")
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        [
+            "Parse \"eighty\" as a setting",
+            "invalid digit found in string"
+        ]
+    );
+}
+
+/// Read config
+#[context(sep = " -> ")]
+fn read_settings() -> Result<String, Error> {
+    Ok(std::fs::read_to_string(
+        "/definitely/missing/settings.toml",
+    )?)
+}
+
+#[test]
+fn separator() {
+    let err = read_settings().unwrap_err();
+    let source = err.iter_causes().next().unwrap().to_string();
+    assert_eq!(err.to_string(), format!("Read config -> {}", source));
+}
+
+/// Find a free port from {start}
+#[context]
+fn free_port(start: u16, taken: &[u16]) -> Result<u16, Error> {
+    let mut port = start;
+    'search: loop {
+        if port == u16::MAX {
+            break 'search Err(failure::err_msg("no free ports"));
+        }
+        for used in taken {
+            if *used == port {
+                port += 1;
+                continue 'search;
+            }
+        }
+        break Ok(port);
+    }
+}
+
+#[test]
+fn loop_break_values() {
+    assert_eq!(free_port(8080, &[8080, 8081]).unwrap(), 8082);
+    assert_eq!(
+        free_port(u16::MAX, &[]).unwrap_err().to_string(),
+        "Find a free port from 65535"
+    );
+}
+
+/// Load the {name} plugin
+#[context]
+fn load_plugin(name: &str) -> Result<&'static str, Error> {
+    #[cfg(feature = "json")]
+    let loader = "json";
+    #[cfg(not(feature = "json"))]
+    let loader = "plain";
+    #[cfg(any())]
+    bail!("never compiled");
+    failure::ensure!(name != "broken", "{} failed to load", name);
+    Ok(loader)
+}
+
+#[test]
+fn cfg_statements() {
+    let loader = match cfg!(feature = "json") {
+        true => "json",
+        false => "plain",
+    };
+    assert_eq!(load_plugin("audit").unwrap(), loader);
+    assert_eq!(
+        load_plugin("broken").unwrap_err().to_string(),
+        "Load the broken plugin"
+    );
+}
+
+/// Reticulate {count} splines
+#[context(verbose)]
+fn reticulate(count: usize) -> Result<usize, Error> {
+    failure::ensure!(count > 0, "nothing to reticulate");
+    Ok(count * 2)
+}
+
+#[test]
+fn verbose_unset() {
+    assert_eq!(reticulate(2).unwrap(), 4);
+    assert_eq!(
+        reticulate(0).unwrap_err().to_string(),
+        "nothing to reticulate"
+    );
+}
+
+/// Parse the ports in {list:?}
+#[context]
+fn parse_ports(list: &str) -> Result<Vec<u16>, Error> {
+    Ok(list
+        .split(',')
+        .map(|port| port.trim().parse::<u16>())
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Sum the sizes in {list:?}
+#[context]
+fn total_size(list: &[&str]) -> Result<u64, Error> {
+    list.iter()
+        .map(|size| size.parse::<u64>().map_err(Error::from))
+        .sum::<Result<u64, _>>()
+}
+
+#[test]
+fn collect_into_result() {
+    assert_eq!(parse_ports("80, 443").unwrap(), [80, 443]);
+    let causes: Vec<String> = parse_ports("80, https")
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        [
+            "Parse the ports in \"80, https\"",
+            "invalid digit found in string"
+        ]
+    );
+    assert_eq!(total_size(&["1", "2"]).unwrap(), 3);
+    assert_eq!(
+        total_size(&["1", "two"]).unwrap_err().to_string(),
+        "Sum the sizes in [\"1\", \"two\"]"
+    );
+}
+
+// The function's generics are emitted as-is, since the body is wrapped in a closure rather than
+// an inner function, so defaults like the ones on this trait are kept where they're allowed.
+#[context]
+trait Decode<T = usize>
+where
+    T: std::str::FromStr,
+    T::Err: failure::Fail,
+{
+    /// Decode {input:?}
+    fn decode(&self, input: &str) -> Result<T, Error> {
+        Ok(input.trim().parse()?)
+    }
+}
+
+struct Decoder;
+
+impl Decode for Decoder {}
+
+impl Decode<bool> for Decoder {}
+
+#[test]
+fn generic_defaults() {
+    assert_eq!(Decode::<usize>::decode(&Decoder, " 7 ").unwrap(), 7);
+    assert!(Decode::<bool>::decode(&Decoder, "true").unwrap());
+    assert_eq!(
+        Decode::<usize>::decode(&Decoder, "seven")
+            .unwrap_err()
+            .to_string(),
+        "Decode \"seven\""
+    );
+}
+
+/// Look up the owner of {key:?}
+#[context]
+fn owner(key: &str) -> Result<String, Error> {
+    let Some((owner, _)) = key.split_once('/') else {
+        return Err(failure::err_msg("keys look like owner/name"));
+    };
+    let Ok(id) = owner.parse::<u32>() else {
+        bail!("{} isn't a user id", owner);
+    };
+    Ok(format!("user {}", id))
+}
+
+#[test]
+fn let_else() {
+    assert_eq!(owner("42/notes").unwrap(), "user 42");
+    assert_eq!(
+        owner("notes").unwrap_err().to_string(),
+        "Look up the owner of \"notes\""
+    );
+    let causes: Vec<String> = owner("alice/notes")
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        [
+            "Look up the owner of \"alice/notes\"",
+            "alice isn't a user id"
+        ]
+    );
+}
+
+/// Walk down to level {depth}
+#[context]
+async fn descend(depth: usize, fail_at: usize) -> Result<usize, Error> {
+    if depth == fail_at {
+        bail!("hit the bottom at {}", depth);
+    }
+    if depth == 5 {
+        return Ok(depth);
+    }
+    Box::pin(descend(depth + 1, fail_at)).await
+}
+
+#[test]
+fn recursive_async() {
+    assert_eq!(block_on(descend(0, 10)).unwrap(), 5);
+    let causes: Vec<String> = block_on(descend(0, 2))
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        [
+            "Walk down to level 0",
+            "Walk down to level 1",
+            "Walk down to level 2",
+            "hit the bottom at 2"
+        ]
+    );
+}
+
+/// Rename {from} to {to}
+#[context(inner_name = "__rename_impl")]
+fn rename(from: &str, to: &str) -> Result<(), Error> {
+    bail!("{} already exists", to);
+}
+
+/// Copy {from}
+#[context(inner_name = "__copy_impl")]
+async fn copy(from: &str) -> Result<usize, Error> {
+    bail!("{} is a directory", from);
+}
+
+#[test]
+fn inner_name() {
+    assert_eq!(rename("a", "b").unwrap_err().to_string(), "Rename a to b");
+    assert_eq!(block_on(copy("src")).unwrap_err().to_string(), "Copy src");
+}
+
+/// Read the header from {name}
+#[context]
+fn read_header(name: &str, mut reader: impl std::io::Read) -> Result<[u8; 4], Error> {
+    let mut header = [0; 4];
+    reader.read_exact(&mut header)?;
+    Ok(header)
+}
+
+/// Count the lines
+#[context]
+async fn count_lines(lines: impl Iterator<Item = &'static str>) -> Result<usize, Error> {
+    let mut count = 0;
+    for line in lines {
+        failure::ensure!(!line.is_empty(), "line {} is empty", count + 1);
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[test]
+fn impl_trait_arguments() {
+    assert_eq!(
+        read_header("a.png", &b"\x89PNG..."[..]).unwrap(),
+        *b"\x89PNG"
+    );
+    let causes: Vec<String> = read_header("b.png", &b"GI"[..])
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        ["Read the header from b.png", "failed to fill whole buffer"]
+    );
+    assert_eq!(
+        block_on(count_lines(vec!["a", "b"].into_iter())).unwrap(),
+        2
+    );
+    assert_eq!(
+        block_on(count_lines(vec!["a", ""].into_iter()))
+            .unwrap_err()
+            .to_string(),
+        "Count the lines"
+    );
+}
+
+/// Resize {name}
+#[context(location, doc, args)]
+fn resize(name: &str, width: u32) -> Result<(), Error> {
+    bail!("{} can't be {} pixels wide", name, width);
+}
+
+/// Upload {name}
+#[context(thread, args, attempt = attempt, prefix = "storage")]
+fn upload(name: &str, attempt: usize) -> Result<(), Error> {
+    bail!("{} is too large", name);
+}
+
+#[test]
+fn combined_options() {
+    let err = resize("logo.png", 0).unwrap_err().to_string();
+    let expected = format!(
+        "Resize logo.png (name = \"logo.png\", width = 0) (at {}:",
+        file!()
+    );
+    assert!(err.starts_with(&expected), "{}", err);
+    let thread = std::thread::Builder::new().name("uploader".into());
+    let err = thread
+        .spawn(|| upload("video.mp4", 2).unwrap_err().to_string())
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(
+        err,
+        "storage: Upload video.mp4 (attempt 2) (name = \"video.mp4\", attempt = 2) (on thread uploader)"
+    );
+}
+
+#[cfg_attr(test, doc = "Parse the manifest")]
+#[context]
+fn cfg_attr_doc() -> Result<(), Error> {
+    bail!("unexpected end of input")
+}
+
+#[context]
+trait Manifest {
+    #[cfg_attr(all(), doc = "Read the manifest")]
+    fn read(&self) -> Result<(), Error> {
+        bail!("no such file")
+    }
+
+    #[cfg_attr(test, doc = "Write the manifest")]
+    #[context]
+    fn write(&self) -> Result<(), Error> {
+        bail!("read-only file system")
+    }
+}
+
+impl Manifest for () {}
+
+#[test]
+fn cfg_attr_docs() {
+    assert_eq!(
+        cfg_attr_doc().unwrap_err().to_string(),
+        "Parse the manifest"
+    );
+    assert_eq!(().read().unwrap_err().to_string(), "Read the manifest");
+    assert_eq!(().write().unwrap_err().to_string(), "Write the manifest");
+}
+
+static SYNC_ERRORS: std::sync::OnceLock<std::sync::mpsc::SyncSender<String>> =
+    std::sync::OnceLock::new();
+
+/// Sync mailbox {id}
+#[context(send_to = SYNC_ERRORS)]
+fn sync_mailbox(id: usize, fail: bool) -> Result<usize, Error> {
+    if fail {
+        bail!("connection reset");
+    }
+    Ok(id)
+}
+
+/// Deliver message {id}
+#[context(send_to = errors, map(std::num::ParseIntError => "Parse message {id}"))]
+fn deliver(id: &str, errors: &std::sync::mpsc::Sender<String>) -> Result<usize, Error> {
+    Ok(id.parse()?)
+}
+
+#[test]
+fn send_to_channel() {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+    SYNC_ERRORS.set(sender).unwrap();
+    assert_eq!(sync_mailbox(1, false).unwrap(), 1);
+    assert!(receiver.try_recv().is_err());
+    assert!(sync_mailbox(2, true).is_err());
+    // The channel is full, so this message is dropped instead of blocking.
+    assert!(sync_mailbox(3, true).is_err());
+    assert_eq!(receiver.try_recv().unwrap(), "Sync mailbox 2");
+    assert!(receiver.try_recv().is_err());
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    assert_eq!(deliver("4", &sender).unwrap(), 4);
+    let err = deliver("x", &sender).unwrap_err();
+    assert_eq!(err.to_string(), "Parse message x");
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["Parse message x"]);
+}
+
+/// Run the service
+#[context]
+#[tokio::main(flavor = "current_thread")]
+async fn context_before_tokio_main() -> Result<(), Error> {
+    let config = tokio::spawn(async { bail!("no config") }).await?;
+    config
+}
+
+/// Run the service
+#[tokio::main(flavor = "current_thread")]
+#[context]
+async fn context_after_tokio_main() -> Result<(), Error> {
+    let config = tokio::spawn(async { bail!("no config") }).await?;
+    config
+}
+
+#[test]
+fn tokio_main() {
+    for result in [context_before_tokio_main(), context_after_tokio_main()] {
+        let causes: Vec<String> = result
+            .unwrap_err()
+            .iter_chain()
+            .map(|cause| cause.to_string())
+            .collect();
+        assert_eq!(causes, ["Run the service", "no config"]);
+    }
+}
+
+struct Upload {
+    file: &'static str,
+    sent: usize,
+}
+
+impl Upload {
+    fn describe_error(&self) -> String {
+        format!("Upload {} after sending {} bytes", self.file, self.sent)
+    }
+
+    fn describe_retry(&self) -> &'static str {
+        "Retry the upload"
+    }
+
+    /// The doc comment isn't used with `with`.
+    #[context(with = self.describe_error)]
+    fn send(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.sent += chunk.len();
+        if self.sent > 4 {
+            bail!("connection reset");
+        }
+        Ok(())
+    }
+
+    #[context(with = self.describe_retry, prefix = "upload")]
+    fn retry(&self) -> Result<(), Error> {
+        bail!("too many retries");
+    }
+}
+
+#[test]
+fn with_method() {
+    let mut upload = Upload {
+        file: "photo.jpg",
+        sent: 0,
+    };
+    upload.send(b"abc").unwrap();
+    let err = upload.send(b"def").unwrap_err();
+    assert_eq!(err.to_string(), "Upload photo.jpg after sending 6 bytes");
+    let err = upload.retry().unwrap_err();
+    assert_eq!(err.to_string(), "upload: Retry the upload");
+}
+
+enum Source {
+    File(&'static str),
+    Env(&'static str),
+    Default,
+}
+
+/// Load the setting
+#[context]
+fn load_setting(source: Source) -> Result<usize, Error> {
+    match source {
+        Source::File(contents) => Ok(contents.trim().parse::<usize>()? * 2),
+        Source::Env(value) => match value.parse::<usize>()? {
+            0 => bail!("the setting can't be zero"),
+            value => Ok(value),
+        },
+        Source::Default => Ok(1),
+    }
+}
+
+#[test]
+fn question_mark_in_match_arms() {
+    assert_eq!(load_setting(Source::File(" 2\n")).unwrap(), 4);
+    assert_eq!(load_setting(Source::Env("3")).unwrap(), 3);
+    assert_eq!(load_setting(Source::Default).unwrap(), 1);
+
+    let causes = |source| -> Vec<String> {
+        load_setting(source)
+            .unwrap_err()
+            .iter_chain()
+            .map(|cause| cause.to_string())
+            .collect()
+    };
+    assert_eq!(
+        causes(Source::File("x")),
+        ["Load the setting", "invalid digit found in string"]
+    );
+    assert_eq!(
+        causes(Source::Env("")),
+        ["Load the setting", "cannot parse integer from empty string"]
+    );
+    assert_eq!(
+        causes(Source::Env("0")),
+        ["Load the setting", "the setting can't be zero"]
+    );
+}
+
+thread_local! {
+    static REQUEST_ID: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
+fn request_id() -> Option<u64> {
+    REQUEST_ID.with(|id| id.get())
+}
+
+/// Charge the card
+#[context(correlation_id = request_id)]
+fn charge(amount: usize) -> Result<usize, Error> {
+    if amount > 100 {
+        bail!("card declined");
+    }
+    Ok(amount)
+}
+
+#[test]
+fn correlation_id() {
+    assert_eq!(charge(200).unwrap_err().to_string(), "Charge the card");
+    REQUEST_ID.with(|id| id.set(Some(42)));
+    assert_eq!(charge(10).unwrap(), 10);
+    assert_eq!(
+        charge(200).unwrap_err().to_string(),
+        "Charge the card (correlation id 42)"
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn correlation_id_task_local() {
+    /// Fetch the order
+    #[context(correlation_id)]
+    async fn fetch_order() -> Result<(), Error> {
+        tokio::task::yield_now().await;
+        bail!("no such order")
+    }
+
+    let err = context_attribute::CORRELATION_ID
+        .scope("req-7".to_string(), fetch_order())
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Fetch the order (correlation id req-7)");
+    let err = fetch_order().await.unwrap_err();
+    assert_eq!(err.to_string(), "Fetch the order");
+}
+
+/// Apply migration {version}
+#[context]
+fn migrate<E: failure::Fail>(version: u32, applied: Result<(), E>) -> Result<u32, Error> {
+    applied?;
+    Ok(version)
+}
+
+#[test]
+fn generic_error_inputs() {
+    assert_eq!(migrate::<std::fmt::Error>(3, Ok(())).unwrap(), 3);
+    let causes: Vec<String> = migrate(4, Err(std::fmt::Error))
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        [
+            "Apply migration 4",
+            "an error occurred when formatting an argument"
+        ]
+    );
+}
+
+/// Read C:\temp\new\{name}.txt \
+#[context]
+fn read_windows_path(name: &str) -> Result<(), Error> {
+    bail!("no such file")
+}
+
+#[doc = "Split on \"\\t\" and \"\\n\":\tthen\njoin"]
+#[context]
+fn split_escapes() -> Result<(), Error> {
+    bail!("no input")
+}
+
+/// Match C:\temp\new \
+#[context(literal)]
+fn literal_backslashes() -> Result<(), Error> {
+    bail!("no match")
+}
+
+#[test]
+fn backslashes_in_doc_comments() {
+    assert_eq!(
+        read_windows_path("a").unwrap_err().to_string(),
+        r"Read C:\temp\new\a.txt \"
+    );
+    assert_eq!(
+        split_escapes().unwrap_err().to_string(),
+        "Split on \"\\t\" and \"\\n\":\tthen\njoin"
+    );
+    assert_eq!(
+        literal_backslashes().unwrap_err().to_string(),
+        r"Match C:\temp\new \"
+    );
+}
+
+/// Authenticate the user
+#[context(debug_msg = "Authenticate {user} against {realm:?}")]
+fn authenticate(user: &str, realm: &str) -> Result<(), Error> {
+    bail!("invalid token")
+}
+
+#[context(debug_msg = "Charge {cents} cents to {card}", msg = "Payment failed")]
+fn pay(cents: usize, card: &str) -> Result<(), Error> {
+    bail!("card declined")
+}
+
+#[test]
+fn debug_messages() {
+    let (auth, payment) = match cfg!(debug_assertions) {
+        true => (
+            r#"Authenticate ada against "staff""#,
+            "Charge 250 cents to visa",
+        ),
+        false => ("Authenticate the user", "Payment failed"),
+    };
+    assert_eq!(authenticate("ada", "staff").unwrap_err().to_string(), auth);
+    assert_eq!(pay(250, "visa").unwrap_err().to_string(), payment);
+}
+
+/// Build an array of {N} ports
+#[context]
+fn build_ports<const N: usize>(inputs: &[&str]) -> Result<[u16; N], Error> {
+    let mut ports = [0; N];
+    for (port, input) in ports.iter_mut().zip(inputs) {
+        *port = input.parse()?;
+    }
+    failure::ensure!(inputs.len() == N, "expected {} ports", N);
+    Ok(ports)
+}
+
+#[test]
+fn const_generic_arrays() {
+    assert_eq!(build_ports::<2>(&["80", "443"]).unwrap(), [80, 443]);
+    let causes = |result: Result<[u16; 3], Error>| -> Vec<String> {
+        result
+            .unwrap_err()
+            .iter_chain()
+            .map(|cause| cause.to_string())
+            .collect()
+    };
+    assert_eq!(
+        causes(build_ports(&["80", "x", "443"])),
+        ["Build an array of 3 ports", "invalid digit found in string"]
+    );
+    assert_eq!(
+        causes(build_ports(&["80"])),
+        ["Build an array of 3 ports", "expected 3 ports"]
+    );
+}
+
+#[context(catalog = "E1001")]
+fn read_coded_config(path: &str) -> Result<String, Error> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+#[context(catalog = "E1002", prefix = "net")]
+fn parse_coded_port(input: &str) -> Result<u16, Error> {
+    Ok(input.trim().parse()?)
+}
+
+#[test]
+fn error_codes() {
+    let err = read_coded_config("missing.toml").unwrap_err();
+    assert_eq!(err.to_string(), "[E1001] Read the config from missing.toml");
+    let err = parse_coded_port("http").unwrap_err();
+    assert_eq!(err.to_string(), "[E1002] net: Parse the port from \"http\"");
+}
+
+async fn query_replica(id: u32) -> Result<String, Error> {
+    tokio::task::yield_now().await;
+    if id == 0 {
+        bail!("no such user");
+    }
+    Ok(format!("user {}", id))
+}
+
+/// Fetch the profile of user {id} from the first replica to answer
+#[context]
+async fn fetch_from_replicas(id: u32) -> Result<String, Error> {
+    tokio::select! {
+        profile = query_replica(id) => Ok(profile?),
+        () = std::future::pending() => unreachable!("this replica never answers"),
+    }
+}
+
+#[tokio::test]
+async fn select_branches() {
+    assert_eq!(fetch_from_replicas(1).await.unwrap(), "user 1");
+    let causes: Vec<String> = fetch_from_replicas(0)
+        .await
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(
+        causes,
+        [
+            "Fetch the profile of user 0 from the first replica to answer",
+            "no such user"
+        ]
+    );
+}
+
+/// Sum the sizes {sizes:?}
+#[context]
+fn sum_sizes(sizes: &[&str]) -> Result<u64, Error> {
+    let sum = sizes
+        .iter()
+        .map(|size| size.parse::<u64>())
+        .sum::<Result<u64, _>>()?;
+    Ok(sum)
+}
+
+/// Multiply the factors {factors:?}
+#[context]
+fn multiply_factors(factors: &[&str]) -> Result<u64, Error> {
+    Ok(factors
+        .iter()
+        .map(|factor| factor.parse::<u64>())
+        .product::<Result<u64, _>>()?)
+}
+
+/// Add up the budget of {items:?}
+#[context]
+fn total_budget(items: &[(&str, u64)]) -> Result<u64, Error> {
+    let total = items.iter().try_fold(0u64, |total, (name, cost)| {
+        total
+            .checked_add(*cost)
+            .ok_or_else(|| failure::format_err!("{} overflows the budget", name))
+    })?;
+    Ok(total)
+}
+
+#[test]
+fn iterator_adapters() {
+    let causes =
+        |err: Error| -> Vec<String> { err.iter_chain().map(|cause| cause.to_string()).collect() };
+    assert_eq!(sum_sizes(&["1", "2"]).unwrap(), 3);
+    assert_eq!(
+        causes(sum_sizes(&["1", "x"]).unwrap_err()),
+        [
+            "Sum the sizes [\"1\", \"x\"]",
+            "invalid digit found in string"
+        ]
+    );
+    assert_eq!(multiply_factors(&["2", "3"]).unwrap(), 6);
+    assert_eq!(
+        causes(multiply_factors(&[""]).unwrap_err()),
+        [
+            "Multiply the factors [\"\"]",
+            "cannot parse integer from empty string"
+        ]
+    );
+    assert_eq!(total_budget(&[("rent", 1), ("food", 2)]).unwrap(), 3);
+    assert_eq!(
+        causes(total_budget(&[("rent", 1), ("yacht", u64::MAX)]).unwrap_err()),
+        [
+            "Add up the budget of [(\"rent\", 1), (\"yacht\", 18446744073709551615)]",
+            "yacht overflows the budget"
+        ]
+    );
+}
+
+/// Open {path}
+#[context(os_error)]
+fn open_device(path: &str) -> Result<(), Error> {
+    match path {
+        "/dev/missing" => Err(std::io::Error::from_raw_os_error(2).into()),
+        "/dev/busy" => Err(std::io::Error::other("device is busy").into()),
+        _ => bail!("{} isn't a device", path),
+    }
+}
+
+#[test]
+fn os_error_codes() {
+    let err = open_device("/dev/missing").unwrap_err();
+    assert_eq!(err.to_string(), "Open /dev/missing (os error 2)");
+    let err = open_device("/dev/busy").unwrap_err();
+    assert_eq!(err.to_string(), "Open /dev/busy");
+    let err = open_device("notes.txt").unwrap_err();
+    assert_eq!(err.to_string(), "Open notes.txt");
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn close(fd: std::os::raw::c_int) -> std::os::raw::c_int;
+}
+
+/// Close file descriptor {fd}
+#[cfg(unix)]
+#[context(errno)]
+fn close_fd(fd: std::os::raw::c_int) -> Result<std::os::raw::c_int, Error> {
+    unsafe { close(fd) }
+}
+
+/// Check the return code {code}
+#[context(errno)]
+fn check_code(code: i64) -> Result<i64, Error> {
+    code
+}
+
+#[test]
+fn errno_return_codes() {
+    assert_eq!(check_code(3).unwrap(), 3);
+    #[cfg(unix)]
+    {
+        let err = close_fd(-1).unwrap_err();
+        assert_eq!(err.to_string(), "Close file descriptor -1");
+        let cause = err.iter_chain().nth(1).unwrap();
+        let cause = cause.downcast_ref::<std::io::Error>().unwrap();
+        // `EBADF`
+        assert_eq!(cause.raw_os_error(), Some(9));
+    }
+}
+
+/// Look up the index of {name}
+#[context(err_if = "result == -1")]
+fn lookup_index(name: &str) -> Result<i32, Error> {
+    Ok(match name {
+        "stdin" => 0,
+        "stdout" => 1,
+        _ => -1,
+    })
+}
+
+/// Read the name of user {id}
+#[context(err_if = "result.is_empty()")]
+fn user_name(id: u32) -> Result<String, Error> {
+    match id {
+        0 => bail!("user 0 is reserved"),
+        1 => Ok("alice".to_string()),
+        _ => Ok(String::new()),
+    }
+}
+
+#[test]
+fn err_if_sentinels() {
+    let causes =
+        |err: Error| -> Vec<String> { err.iter_chain().map(|cause| cause.to_string()).collect() };
+    assert_eq!(lookup_index("stdout").unwrap(), 1);
+    assert_eq!(
+        causes(lookup_index("tty").unwrap_err()),
+        [
+            "Look up the index of tty",
+            "the function returned a value matching `result == -1`"
+        ]
+    );
+    assert_eq!(user_name(1).unwrap(), "alice");
+    assert_eq!(
+        causes(user_name(2).unwrap_err()),
+        [
+            "Read the name of user 2",
+            "the function returned a value matching `result.is_empty()`"
+        ]
+    );
+    assert_eq!(
+        causes(user_name(0).unwrap_err()),
+        ["Read the name of user 0", "user 0 is reserved"]
+    );
+}
+
+/// Load the theme from {path}
+#[context(or_else = "light".to_string(), send_to = errors)]
+fn load_theme(path: &str, errors: &std::sync::mpsc::Sender<String>) -> Result<String, Error> {
+    match path {
+        "dark.toml" => Ok("dark".to_string()),
+        _ => bail!("no such theme"),
+    }
+}
+
+/// Load the font size
+#[context(or_else = 12, level = "info")]
+fn load_font_size(size: &str) -> Result<usize, Error> {
+    Ok(size.parse()?)
+}
+
+#[test]
+fn or_else_fallbacks() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    assert_eq!(load_theme("dark.toml", &sender).unwrap(), "dark");
+    assert!(receiver.try_recv().is_err());
+    assert_eq!(load_theme("neon.toml", &sender).unwrap(), "light");
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        "Load the theme from neon.toml"
+    );
+    assert_eq!(load_font_size("14").unwrap(), 14);
+    assert_eq!(load_font_size("huge").unwrap(), 12);
+}
+
+struct Inventory {
+    items: Vec<&'static str>,
+}
+
+macro_rules! generated_lookup {
+    () => {
+        fn generated(&self, index: usize) -> Result<&'static str, Error> {
+            match self.items.get(index) {
+                Some(item) => Ok(item),
+                None => bail!("no item {}", index),
+            }
+        }
+    };
+}
+
+#[context(prefix = "inventory")]
+impl Inventory {
+    /// Find item {index}
+    fn find(&self, index: usize) -> Result<&'static str, Error> {
+        match self.items.get(index) {
+            Some(item) => Ok(item),
+            None => bail!("no item {}", index),
+        }
+    }
+
+    /// Count the items
+    #[context(skip)]
+    fn count(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Find the last item
+    fn last(&self) -> Fallible<&'static str> {
+        match self.items.last() {
+            Some(item) => Ok(item),
+            None => bail!("no items"),
+        }
+    }
+
+    /// Find item {index} later
+    fn find_later(&self, index: usize) -> impl std::future::Future<Output = Fallible<usize>> {
+        let found = self.items.get(index).map(|item| item.len());
+        async move {
+            match found {
+                Some(len) => Ok(len),
+                None => bail!("no item {}", index),
+            }
+        }
+    }
+
+    #[context(skip)]
+    fn skipped(&self, index: usize) -> Result<&'static str, Error> {
+        match self.items.get(index) {
+            Some(item) => Ok(item),
+            None => bail!("no item {}", index),
+        }
+    }
+
+    /// Remove item {index}
+    #[context]
+    fn remove(&mut self, index: usize) -> Result<&'static str, Error> {
+        match index < self.items.len() {
+            true => Ok(self.items.remove(index)),
+            false => bail!("no item {}", index),
+        }
+    }
+
+    generated_lookup!();
+}
+
+#[test]
+fn impl_blocks_with_skipped_methods() {
+    let mut inventory = Inventory {
+        items: vec!["lamp", "rope"],
+    };
+    assert_eq!(inventory.find(1).unwrap(), "rope");
+    assert_eq!(
+        inventory.find(2).unwrap_err().to_string(),
+        "inventory: Find item 2"
+    );
+    assert_eq!(inventory.count(), 2);
+    assert_eq!(block_on(inventory.find_later(0)).unwrap(), 4);
+    assert_eq!(
+        block_on(inventory.find_later(2)).unwrap_err().to_string(),
+        "inventory: Find item 2 later"
+    );
+    assert_eq!(inventory.skipped(2).unwrap_err().to_string(), "no item 2");
+    assert_eq!(inventory.generated(2).unwrap_err().to_string(), "no item 2");
+    // A method's own `#[context]` replaces the block's options.
+    assert_eq!(
+        inventory.remove(2).unwrap_err().to_string(),
+        "Remove item 2"
+    );
+    assert_eq!(inventory.remove(0).unwrap(), "lamp");
+    assert_eq!(inventory.remove(0).unwrap(), "rope");
+    assert_eq!(
+        inventory.last().unwrap_err().to_string(),
+        "inventory: Find the last item"
+    );
+}
+
+struct Mirror;
+
+// `#[async_trait]` expands first, and hands the impl methods returning boxed futures.
+#[async_trait::async_trait]
+#[context]
+impl Fetch for Mirror {
+    /// Fetch from the mirror
+    async fn fetch(&self, fail: bool) -> Result<usize, Error> {
+        Ok(some_future(fail).await? * 10)
+    }
+}
+
+#[test]
+fn impl_blocks_with_async_trait() {
+    assert_eq!(block_on(Mirror.fetch(false)).unwrap(), 20);
+    let err = block_on(Mirror.fetch(true)).unwrap_err();
+    let causes: Vec<String> = err.iter_chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(causes, ["Fetch from the mirror", "future failed"]);
+}
+
+/// Flush the journal
+#[context]
+fn flush_journal(log: &std::cell::RefCell<Vec<&'static str>>, fail: bool) -> Result<(), Error> {
+    log.borrow_mut().push("lock");
+    scopeguard::defer! {
+        log.borrow_mut().push("unlock");
+    }
+    log.borrow_mut().push("write");
+    if fail {
+        bail!("disk full");
+    }
+    log.borrow_mut().push("sync");
+    Ok(())
+}
+
+#[test]
+fn scopeguard_defer() {
+    let log = std::cell::RefCell::new(vec![]);
+    flush_journal(&log, false).unwrap();
+    assert_eq!(*log.borrow(), ["lock", "write", "sync", "unlock"]);
+
+    log.borrow_mut().clear();
+    let err = flush_journal(&log, true).unwrap_err();
+    // The guard runs when the body returns, before the function returns the error.
+    assert_eq!(*log.borrow(), ["lock", "write", "unlock"]);
+    let causes: Vec<String> = err.iter_chain().map(|cause| cause.to_string()).collect();
+    assert_eq!(causes, ["Flush the journal", "disk full"]);
+}
+
+/// Rebuild the search index from scratch, which reads every document in the store and can take a
+/// while on large stores
+#[context(max_len = 40)]
+fn rebuild_index() -> Result<(), Error> {
+    bail!("store is offline");
+}
+
+/// Reindex the documents of {owner}, which reads each of them again
+#[context(max_len = 30)]
+fn reindex(owner: &str) -> Result<(), Error> {
+    bail!("{} has no documents", owner);
+}
+
+/// Reindex {owner}
+#[context(max_len = 30)]
+fn reindex_short(owner: &str) -> Result<(), Error> {
+    bail!("{} has no documents", owner);
+}
+
+/// Rebuild the search index
+#[context(max_len = 5, id, prefix = "search")]
+fn rebuild_prefixed() -> Result<(), Error> {
+    bail!("store is offline");
+}
+
+/// Reindex {owner}
+#[context(max_len = 5, prefix = "search", args)]
+fn reindex_prefixed(owner: &str) -> Result<(), Error> {
+    bail!("{} has no documents", owner);
+}
+
+#[test]
+fn max_len_truncates() {
+    assert_eq!(
+        rebuild_index().unwrap_err().to_string(),
+        "Rebuild the search index from scratch, …"
+    );
+    assert_eq!(
+        reindex("alice").unwrap_err().to_string(),
+        "Reindex the documents of alic…"
+    );
+    assert_eq!(
+        reindex_short("alice").unwrap_err().to_string(),
+        "Reindex alice"
+    );
+    // Only the message itself is truncated, and not its prefixes and suffixes.
+    let msg = rebuild_prefixed().unwrap_err().to_string();
+    assert!(msg.starts_with("[E#"), "{}", msg);
+    assert_eq!(&msg[9..], "search: Rebu…");
+    assert_eq!(
+        reindex_prefixed("alice").unwrap_err().to_string(),
+        r#"search: Rein… (owner = "alice")"#
+    );
+}
+
+/// Import the users
+#[context(batch)]
+fn import_users(rows: &[&str]) -> Result<Vec<u32>, Error> {
+    if rows.is_empty() {
+        bail!("nothing to import");
+    }
+    let mut ids = vec![];
+    for row in rows {
+        if row.is_empty() {
+            continue;
+        }
+        let id = row.trim().parse::<u32>()?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// Resize the images
+#[context(batch)]
+async fn resize_images(sizes: Vec<&str>) -> Result<u32, Error> {
+    let mut total = 0;
+    for size in sizes {
+        total += futures::future::ready(size.parse::<u32>()).await?;
+    }
+    Ok(total)
+}
+
+#[test]
+fn batch_items() {
+    assert_eq!(import_users(&["1", "", " 2"]).unwrap(), [1, 2]);
+    let causes =
+        |err: Error| -> Vec<String> { err.iter_chain().map(|cause| cause.to_string()).collect() };
+    assert_eq!(
+        causes(import_users(&["1", "", "x", "4"]).unwrap_err()),
+        ["Import the users: item 2", "invalid digit found in string"]
+    );
+    assert_eq!(
+        causes(import_users(&[]).unwrap_err()),
+        ["Import the users", "nothing to import"]
+    );
+    assert_eq!(block_on(resize_images(vec!["2", "3"])).unwrap(), 5);
+    assert_eq!(
+        causes(block_on(resize_images(vec!["2", "-3"])).unwrap_err()),
+        ["Resize the images: item 1", "invalid digit found in string"]
+    );
+}
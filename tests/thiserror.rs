@@ -0,0 +1,134 @@
+use context_attribute::context;
+use std::error::Error as _;
+
+#[derive(Debug, thiserror::Error)]
+enum ConfigError {
+    #[error("{0}")]
+    Io(String, #[source] std::io::Error),
+    #[error("{0}")]
+    Parse(String, #[source] std::num::ParseIntError),
+}
+
+/// Read the config from {path}
+#[context(variant = ConfigError::Io)]
+fn read_config(path: &str) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path)
+}
+
+/// Parse the port
+#[context(variant = ConfigError::Parse)]
+async fn parse_port(input: &str) -> Result<u16, ConfigError> {
+    let port = input.parse()?;
+    Ok(port)
+}
+
+#[test]
+fn thiserror_variant() {
+    let err = read_config("does-not-exist.txt").unwrap_err();
+    assert!(matches!(err, ConfigError::Io(..)));
+    assert_eq!(err.to_string(), "Read the config from does-not-exist.txt");
+    assert!(err.source().unwrap().is::<std::io::Error>());
+
+    assert_eq!(futures::executor::block_on(parse_port("80")).unwrap(), 80);
+    let err = futures::executor::block_on(parse_port("x")).unwrap_err();
+    assert!(matches!(err, ConfigError::Parse(..)));
+    assert_eq!(err.to_string(), "Parse the port");
+}
+
+/// Read the config later
+#[context(variant = ConfigError::Io)]
+fn read_config_later() -> impl std::future::Future<Output = Result<String, ConfigError>> {
+    async { std::fs::read_to_string("does-not-exist.txt") }
+}
+
+#[test]
+fn thiserror_impl_future() {
+    let err = futures::executor::block_on(read_config_later()).unwrap_err();
+    assert!(matches!(err, ConfigError::Io(..)));
+    assert_eq!(err.to_string(), "Read the config later");
+}
+
+#[derive(Debug, thiserror::Error)]
+enum StoreError {
+    #[error("{context} ({path:?})")]
+    Open {
+        context: String,
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to write {bytes} bytes to {path:?}")]
+    Write {
+        path: std::path::PathBuf,
+        bytes: usize,
+        source: std::io::Error,
+    },
+}
+
+/// Open the store
+#[context(variant = StoreError::Open { path, context })]
+fn open_store(path: std::path::PathBuf) -> Result<String, StoreError> {
+    std::fs::read_to_string(&path)
+}
+
+/// Write to the store
+#[context(variant = StoreError::Write { path: path.into(), bytes: data.len() })]
+fn write_store(path: &str, data: &[u8]) -> Result<(), StoreError> {
+    std::fs::write(format!("does-not-exist/{}", path), data)
+}
+
+#[test]
+fn thiserror_struct_variant() {
+    let err = open_store("does-not-exist.db".into()).unwrap_err();
+    match &err {
+        StoreError::Open { context, path, .. } => {
+            assert_eq!(context, "Open the store");
+            assert_eq!(path, std::path::Path::new("does-not-exist.db"));
+        }
+        err => panic!("unexpected error {:?}", err),
+    }
+    assert_eq!(err.to_string(), r#"Open the store ("does-not-exist.db")"#);
+    assert!(err.source().unwrap().is::<std::io::Error>());
+
+    let err = write_store("store.db", b"abc").unwrap_err();
+    assert!(matches!(err, StoreError::Write { bytes: 3, .. }));
+    assert_eq!(err.to_string(), r#"failed to write 3 bytes to "store.db""#);
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("timed out after {0}ms")]
+struct Elapsed(u64);
+
+#[derive(Debug, Clone, thiserror::Error)]
+enum FetchError {
+    #[error("{0}")]
+    Timeout(String, #[source] Elapsed),
+}
+
+/// Fetch {url}
+#[context(variant = FetchError::Timeout, eprintln, level = "warn")]
+fn fetch(url: &str, log: &mut Vec<Elapsed>) -> Result<String, FetchError> {
+    let err = Elapsed(url.len() as u64);
+    // Keep a copy of the error, and return the original.
+    log.push(err.clone());
+    Err(err)
+}
+
+#[test]
+fn cloned_errors_after_logging() {
+    let mut log = vec![];
+    let err = fetch("example.com", &mut log).unwrap_err();
+    let logged = err.clone();
+    let chain = |err: &FetchError| -> Vec<String> {
+        let mut chain = vec![err.to_string()];
+        let mut source = err.source();
+        while let Some(cause) = source {
+            chain.push(cause.to_string());
+            source = cause.source();
+        }
+        chain
+    };
+    assert_eq!(chain(&err), ["Fetch example.com", "timed out after 11ms"]);
+    assert_eq!(chain(&logged), chain(&err));
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].to_string(), "timed out after 11ms");
+}
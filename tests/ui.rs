@@ -0,0 +1,25 @@
+#[test]
+fn ui() {
+    // Set for the allowlist check of `prefix`, in `fail/unknown_prefix.rs`.
+    std::env::set_var("CONTEXT_ATTRIBUTE_PREFIXES", "storage, network");
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass/*.rs");
+    t.compile_fail("tests/ui/fail/*.rs");
+    // These errors list the implementations of the enabled backends, so only check the defaults.
+    if cfg!(not(any(
+        feature = "anyhow",
+        feature = "eyre",
+        feature = "miette",
+        feature = "syn"
+    ))) {
+        t.compile_fail("tests/ui/unsupported/*.rs");
+    }
+    if cfg!(feature = "strict") {
+        t.compile_fail("tests/ui/strict/*.rs");
+        if cfg!(feature = "lint-infallible") {
+            t.compile_fail("tests/ui/infallible/*.rs");
+        }
+    } else {
+        t.pass("tests/ui/lenient/*.rs");
+    }
+}
@@ -0,0 +1,11 @@
+use context_attribute::context;
+
+#[context]
+trait Plugin {
+    #[cfg_attr(feature = "json", doc = "Load the plugin")]
+    fn load(&self) -> Result<(), failure::Error> {
+        failure::bail!("no such plugin")
+    }
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use context_attribute::context;
+
+/// Apply the migration
+#[context]
+fn migrate<E: Into<failure::Error>>(applied: Result<(), E>) -> Result<(), E> {
+    applied
+}
+
+/// Apply the migration later
+#[context]
+async fn migrate_later<E>(applied: Result<(), E>) -> Result<(), E> {
+    applied
+}
+
+fn main() {}
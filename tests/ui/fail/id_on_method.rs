@@ -0,0 +1,13 @@
+use context_attribute::context;
+
+struct Users;
+
+impl Users {
+    /// Load the users
+    #[context(id)]
+    fn load(&self) -> Result<(), failure::Error> {
+        failure::bail!("no such table")
+    }
+}
+
+fn main() {}
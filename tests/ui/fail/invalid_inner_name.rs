@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Rename the file
+#[context(inner_name = "fn")]
+fn rename() -> Result<(), failure::Error> {
+    failure::bail!("already exists")
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Do the thing
+#[context(join = '\n')]
+fn invalid_join() -> Result<(), failure::Error> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use context_attribute::context;
+
+/// Refresh the cache
+#[context(eprintln, level = "loud")]
+fn refresh() -> Result<(), failure::Error> {
+    failure::bail!("already fresh")
+}
+
+/// Refresh the index
+#[context(level = "warn")]
+fn reindex() -> Result<(), failure::Error> {
+    failure::bail!("already fresh")
+}
+
+fn main() {}
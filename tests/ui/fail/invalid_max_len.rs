@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Compact the log
+#[context(max_len = 0)]
+fn compact() -> Result<(), failure::Error> {
+    failure::bail!("log is locked")
+}
+
+fn main() {}
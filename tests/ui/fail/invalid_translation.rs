@@ -0,0 +1,14 @@
+use context_attribute::context;
+
+#[context(t = "missing key")]
+fn bad_key() -> Result<(), failure::Error> {
+    Ok(())
+}
+
+/// No key to translate
+#[context(t_fn = my::tr)]
+fn missing_key() -> Result<(), failure::Error> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use context_attribute::context;
+
+#[context]
+fn undocumented() -> Result<(), failure::Error> {
+    failure::bail!("no docs")
+}
+
+fn main() {}
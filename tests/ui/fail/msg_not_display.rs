@@ -0,0 +1,10 @@
+use context_attribute::context;
+
+struct Service;
+
+#[context(msg = Service)]
+fn start() -> Result<(), failure::Error> {
+    failure::bail!("already running")
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+#![deny(unused_must_use)]
+
+use context_attribute::context;
+
+/// Find the user
+#[context]
+#[must_use = "the user may not exist"]
+fn find_user(id: u32) -> Option<u32> {
+    Some(id)
+}
+
+/// Delete the user
+#[must_use = "deleting the user may fail"]
+#[context]
+fn delete_user(id: u32) -> Result<(), failure::Error> {
+    failure::ensure!(id != 0, "no user with id 0");
+    Ok(())
+}
+
+fn main() {
+    find_user(1);
+    delete_user(1);
+}
@@ -0,0 +1,17 @@
+use context_attribute::context;
+
+/// The answer
+#[context]
+const ANSWER: usize = 42;
+
+/// A config file
+#[context]
+struct Config {
+    path: String,
+}
+
+fn main() {
+    let _ = Config {
+        path: ANSWER.to_string(),
+    };
+}
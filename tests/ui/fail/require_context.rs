@@ -0,0 +1,44 @@
+use context_attribute::require_context;
+
+require_context! {
+    mod api {
+        use context_attribute::context;
+        use failure::Error;
+
+        /// Read the config
+        #[context]
+        pub fn read_config() -> Result<String, Error> {
+            Ok(std::fs::read_to_string("config.toml")?)
+        }
+
+        pub fn parse_config(input: &str) -> Result<usize, Error> {
+            Ok(input.parse()?)
+        }
+
+        pub struct Client;
+
+        impl Client {
+            pub fn connect(&self) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+
+        pub mod nested {
+            pub fn load() -> Result<(), failure::Error> {
+                Ok(())
+            }
+        }
+
+        pub struct Server;
+
+        #[context]
+        impl Server {
+            /// Start the server
+            pub fn start(&self) -> Result<(), Error> {
+                failure::bail!("address in use")
+            }
+        }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Process job {self.id}
+#[context]
+fn process() -> Result<(), failure::Error> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use context_attribute::context;
+
+/// Parse {input
+#[context]
+fn parse(input: &str) -> Result<(), failure::Error> {
+    let _ = input;
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use context_attribute::context;
+
+#[context(catalog = "E9999")]
+fn write() -> Result<(), failure::Error> {
+    failure::bail!("disk full");
+}
+
+fn main() {}
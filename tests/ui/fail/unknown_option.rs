@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Do the thing
+#[context(frobnicate)]
+fn unknown() -> Result<(), failure::Error> {
+    Ok(())
+}
+
+fn main() {}
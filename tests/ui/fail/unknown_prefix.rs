@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Write the file
+#[context(prefix = "disk")]
+fn write() -> Result<(), failure::Error> {
+    failure::bail!("disk full");
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use context_attribute::context;
+
+#[derive(Debug)]
+enum ConfigError {
+    Io(String, std::io::Error),
+}
+
+/// Read the config
+#[context(variant = ConfigError::Io, map(std::io::Error => "disk problem"))]
+fn read_config() -> Result<String, ConfigError> {
+    std::fs::read_to_string("config.toml")
+}
+
+fn main() {}
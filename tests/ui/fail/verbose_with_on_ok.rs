@@ -0,0 +1,11 @@
+use context_attribute::context;
+
+fn audit(_: &str) {}
+
+/// Delete the account
+#[context(verbose, on_ok = audit)]
+fn delete() -> Result<(), failure::Error> {
+    failure::bail!("account is locked")
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use context_attribute::context;
+
+#[context(with = self.describe_error)]
+fn upload() -> Result<(), failure::Error> {
+    failure::bail!("connection reset")
+}
+
+#[context(with = describe_error)]
+fn download() -> Result<(), failure::Error> {
+    failure::bail!("connection reset")
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+use context_attribute::context;
+use failure::Error;
+
+/// Square a number
+#[context]
+fn square(num: usize) -> Result<usize, Error> {
+    Ok(num * num)
+}
+
+/// Parse a number
+#[context]
+fn parse(input: &str) -> Result<usize, Error> {
+    Ok(input.parse()?)
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Does nothing, so there is no context to add.
+#[context]
+fn noop() {}
+
+fn main() {
+    noop();
+}
@@ -0,0 +1,20 @@
+use context_attribute::context;
+use failure::{ensure, Error};
+
+/// Square a number if it's less than 10.
+#[context]
+fn square(num: usize) -> Result<usize, Error> {
+    ensure!(num < 10, "Number was too large");
+    Ok(num * num)
+}
+
+/// Read {path} from disk
+#[context]
+pub async fn read(path: &str) -> Result<String, Error> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+fn main() {
+    let _ = square(2);
+    let _ = read("address.txt");
+}
@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+///
+#[context]
+fn empty() -> Result<(), failure::Error> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use context_attribute::context;
+
+/// Do nothing
+#[context]
+fn noop() {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use context_attribute::context;
+
+#[context(optional_doc)]
+fn load() -> Result<(), failure::Error> {
+    failure::bail!("no config");
+}
+
+fn main() {}
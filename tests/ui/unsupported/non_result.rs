@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Square a number
+#[context]
+fn square(num: usize) -> usize {
+    num * num
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use context_attribute::context;
+
+/// Parse the input
+#[context]
+fn parse(input: &str) -> Result<usize, &'static str> {
+    input.parse().map_err(|_| "not a number")
+}
+
+fn main() {}
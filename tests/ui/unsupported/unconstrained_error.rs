@@ -0,0 +1,15 @@
+use context_attribute::context;
+
+trait Service {
+    type Error;
+
+    fn call(&self) -> Result<(), Self::Error>;
+}
+
+/// Run the service
+#[context]
+fn run<S: Service>(service: S) -> Result<(), S::Error> {
+    service.call()
+}
+
+fn main() {}
@@ -0,0 +1,25 @@
+//! Contexts that are only attached when `CONTEXT_VERBOSE` is set.
+//!
+//! The variable is cached for the whole test binary, so this file has a single test. Without it
+//! set, the error passes through as-is, which `tests/test.rs` checks.
+
+use context_attribute::context;
+
+/// Reticulate {count} splines
+#[context(verbose)]
+fn reticulate(count: usize) -> Result<usize, failure::Error> {
+    failure::ensure!(count > 0, "nothing to reticulate");
+    Ok(count * 2)
+}
+
+#[test]
+fn verbose() {
+    std::env::set_var("CONTEXT_VERBOSE", "1");
+    assert_eq!(reticulate(2).unwrap(), 4);
+    let causes: Vec<String> = reticulate(0)
+        .unwrap_err()
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect();
+    assert_eq!(causes, ["Reticulate 0 splines", "nothing to reticulate"]);
+}